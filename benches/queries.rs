@@ -0,0 +1,104 @@
+//! Runs the game's query set against the different storage backends.
+//!
+//! `cargo bench --bench queries`
+
+use std::{hint::black_box, time::Instant};
+
+use macroquad::math::Vec2;
+use rustling::{
+    components::{
+        collider::Collider, enemy::Enemy, player::Player, position::Position, velocity::Velocity,
+    },
+    storage::StorageType,
+    world::World,
+};
+
+const OBJECTS: usize = 1500;
+const ENEMIES: usize = 100;
+const ITERATIONS: u32 = 1000;
+
+fn collider() -> Collider {
+    Collider {
+        collision_offset: Vec2::new(17., 38.),
+        collision_size: Vec2::new(14., 5.),
+        sprite_padding: Vec2::new(18.0, 20.0),
+        visible_size: Vec2::new(18.0, 26.0),
+    }
+}
+
+fn build_world(default_storage: StorageType, sparse_markers: bool) -> World {
+    let mut world = World::with_default_storage(default_storage);
+    if sparse_markers {
+        world.register_component::<Player>(StorageType::SparseSet);
+        world.register_component::<Enemy>(StorageType::SparseSet);
+    }
+
+    // static tile objects, like the ones add_object creates from the map
+    for i in 0..OBJECTS {
+        world
+            .spawn_entity()
+            .with(Position {
+                x: i as f32,
+                y: i as f32,
+            })
+            .with(collider());
+    }
+
+    for i in 0..ENEMIES {
+        world
+            .spawn_entity()
+            .with(Position { x: i as f32, y: 0. })
+            .with(Velocity { x: 8.0, y: 8.0 })
+            .with(collider())
+            .with(Enemy::default());
+    }
+
+    world
+        .spawn_entity()
+        .with(Position { x: 0., y: 0. })
+        .with(Velocity::default())
+        .with(collider())
+        .with(Player::default());
+
+    world
+}
+
+fn run_queries(world: &mut World) -> usize {
+    let mut rows = 0;
+    rows += black_box(world.query::<(&Position, &Collider, &Enemy)>()).count();
+    rows += black_box(world.query::<(&mut Velocity, &mut Player)>()).count();
+    rows += black_box(world.query::<(&Position, &Collider, &mut Enemy)>()).count();
+    rows += black_box(world.query::<(&mut Position, &Velocity, &Collider)>()).count();
+    rows += black_box(world.query::<(&Position, &Player)>()).count();
+    rows += black_box(world.query::<(&Position, &mut Player)>()).count();
+    rows += black_box(world.query::<(&Position, &mut Enemy)>()).count();
+    rows += black_box(world.query::<&mut Enemy>()).count();
+    rows += black_box(world.query::<&mut Player>()).count();
+    rows += black_box(world.query::<(&Position, &mut Velocity, &mut Enemy)>()).count();
+    rows
+}
+
+fn bench(label: &str, mut world: World) {
+    let start = Instant::now();
+    let mut rows = 0;
+    for _ in 0..ITERATIONS {
+        rows += run_queries(&mut world);
+    }
+    let elapsed = start.elapsed();
+
+    println!(
+        "{:<32} {:>10.2?} per frame ({} rows)",
+        label,
+        elapsed / ITERATIONS,
+        rows / ITERATIONS as usize
+    );
+}
+
+fn main() {
+    bench("dense", build_world(StorageType::Dense, false));
+    bench(
+        "dense, sparse Player/Enemy",
+        build_world(StorageType::Dense, true),
+    );
+    bench("sparse", build_world(StorageType::SparseSet, false));
+}
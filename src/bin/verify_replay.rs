@@ -0,0 +1,24 @@
+//! Plays a replay without a window and checks it ends the way it did when
+//! it was recorded: `cargo run --bin verify_replay -- replay.json`
+
+use std::process::ExitCode;
+
+use rustling::replay::{self, Replay};
+
+fn main() -> ExitCode {
+    let Some(path) = std::env::args().nth(1) else {
+        eprintln!("usage: verify_replay <replay.json>");
+        return ExitCode::FAILURE;
+    };
+
+    match Replay::load(&path).and_then(|replay| replay::verify(&replay)) {
+        Ok(checksum) => {
+            println!("{}: ok, checksum {}", path, checksum);
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            eprintln!("{}: {}", path, e);
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,102 @@
+use crate::{entity::entity::Entity, world::World};
+
+type Command = Box<dyn FnOnce(&mut World) + Send>;
+
+/// Structural changes recorded while a system is iterating a query, applied
+/// later at a sync point. Systems build one locally, hand it over with
+/// `World::defer` once the query is done, and the main loop runs
+/// `World::apply_commands` after all systems. Commands are `Send` since
+/// parallel systems record them on worker threads.
+#[derive(Default)]
+pub struct Commands {
+    queue: Vec<Command>,
+}
+
+impl Commands {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Spawns an entity with the components added through the returned builder.
+    pub fn spawn(&mut self) -> EntityCommands<'_> {
+        EntityCommands {
+            commands: self,
+            components: Vec::new(),
+        }
+    }
+
+    pub fn despawn(&mut self, entity: Entity) {
+        self.add(move |world| {
+            world.despawn(entity);
+        });
+    }
+
+    pub fn insert<ComponentType: Send + 'static>(
+        &mut self,
+        entity: Entity,
+        component: ComponentType,
+    ) {
+        self.add(move |world| world.add_component_to_entity(entity, component));
+    }
+
+    pub fn remove<ComponentType: 'static>(&mut self, entity: Entity) {
+        self.add(move |world| {
+            world.remove_component::<ComponentType>(entity);
+        });
+    }
+
+    pub fn set_parent(&mut self, child: Entity, parent: Entity) {
+        self.add(move |world| {
+            world.set_parent(child, parent);
+        });
+    }
+
+    /// Runs arbitrary code against the world at the sync point.
+    pub fn add(&mut self, command: impl FnOnce(&mut World) + Send + 'static) {
+        self.queue.push(Box::new(command));
+    }
+
+    pub fn append(&mut self, other: &mut Commands) {
+        self.queue.append(&mut other.queue);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    /// Applies the commands in the order they were recorded.
+    pub fn apply(&mut self, world: &mut World) {
+        for command in self.queue.drain(..) {
+            command(world);
+        }
+    }
+}
+
+type InsertComponent = Box<dyn FnOnce(&mut World, Entity) + Send>;
+
+/// Collects components for `Commands::spawn`, queued when dropped.
+pub struct EntityCommands<'a> {
+    commands: &'a mut Commands,
+    components: Vec<InsertComponent>,
+}
+
+impl EntityCommands<'_> {
+    pub fn with<ComponentType: Send + 'static>(mut self, component: ComponentType) -> Self {
+        self.components.push(Box::new(move |world, entity| {
+            world.add_component_to_entity(entity, component)
+        }));
+        self
+    }
+}
+
+impl Drop for EntityCommands<'_> {
+    fn drop(&mut self) {
+        let components = std::mem::take(&mut self.components);
+        self.commands.add(move |world| {
+            let entity = world.add_entity();
+            for insert in components {
+                insert(world, entity);
+            }
+        });
+    }
+}
@@ -1,8 +1,63 @@
-use macroquad::math::Vec2;
+use macroquad::math::{Rect, Vec2};
+use nanoserde::{DeJson, SerJson};
 
+#[derive(Clone)]
 pub struct Collider {
     pub collision_offset: Vec2, // feet collision offset -> for object collisions
     pub collision_size: Vec2,
     pub sprite_padding: Vec2,
     pub visible_size: Vec2,
 }
+
+impl Collider {
+    /// Feet box at `position`, what gets blocked by the map.
+    pub fn collision_box(&self, position: Vec2) -> Rect {
+        Rect::new(
+            position.x + self.collision_offset.x,
+            position.y + self.collision_offset.y,
+            self.collision_size.x,
+            self.collision_size.y,
+        )
+    }
+
+    /// Body box at `position`, what attacks hit.
+    pub fn hurtbox(&self, position: Vec2) -> Rect {
+        Rect::new(
+            position.x + self.sprite_padding.x,
+            position.y + self.sprite_padding.y,
+            self.collision_size.x,
+            self.collision_size.y,
+        )
+    }
+}
+
+/// `Collider` as it's written in save and definition files.
+#[derive(Debug, Clone, SerJson, DeJson)]
+pub struct ColliderData {
+    pub collision_offset: [f32; 2],
+    pub collision_size: [f32; 2],
+    pub sprite_padding: [f32; 2],
+    pub visible_size: [f32; 2],
+}
+
+impl From<&Collider> for ColliderData {
+    fn from(collider: &Collider) -> Self {
+        Self {
+            collision_offset: collider.collision_offset.into(),
+            collision_size: collider.collision_size.into(),
+            sprite_padding: collider.sprite_padding.into(),
+            visible_size: collider.visible_size.into(),
+        }
+    }
+}
+
+impl From<&ColliderData> for Collider {
+    fn from(data: &ColliderData) -> Self {
+        Self {
+            collision_offset: data.collision_offset.into(),
+            collision_size: data.collision_size.into(),
+            sprite_padding: data.sprite_padding.into(),
+            visible_size: data.visible_size.into(),
+        }
+    }
+}
@@ -0,0 +1,17 @@
+use nanoserde::{DeJson, SerJson};
+
+/// Sides the entity ran into during the last fixed step, the map or the
+/// edge of the world. Written by `movement_systems`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, SerJson, DeJson)]
+pub struct Contact {
+    pub up: bool,
+    pub down: bool,
+    pub left: bool,
+    pub right: bool,
+}
+
+impl Contact {
+    pub fn any(&self) -> bool {
+        self.up || self.down || self.left || self.right
+    }
+}
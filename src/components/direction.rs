@@ -1,4 +1,6 @@
-#[derive(Debug)]
+use nanoserde::{DeJson, SerJson};
+
+#[derive(Debug, Clone, SerJson, DeJson)]
 pub enum Direction {
     Up,
     Down,
@@ -1,11 +1,13 @@
-use crate::components::{sprite::Sprite, velocity::Velocity};
-use macroquad::{
-    math::Vec2,
-    prelude::animation::{AnimatedSprite, Animation},
+use crate::components::{
+    collider::ColliderData,
+    sprite::{advance_animation, Sprite, SpriteSheet},
+    velocity::Velocity,
 };
+use macroquad::math::Vec2;
+use nanoserde::{DeJson, SerJson};
 use rand::prelude::*;
 
-#[derive(Debug)]
+#[derive(Debug, Clone, SerJson, DeJson)]
 pub enum AIType {
     Attack,
     ChasePlayer,
@@ -13,6 +15,7 @@ pub enum AIType {
     Dead,
 }
 
+#[derive(Debug, Clone, SerJson, DeJson)]
 pub struct Enemy {
     pub walk_speed: f32,
     pub chase_speed: f32,
@@ -55,8 +58,7 @@ impl Default for Enemy {
 }
 
 impl Enemy {
-    pub fn change_direction(&self, velocity: &mut Velocity, walk_speed: f32) {
-        let mut rng = rand::rng();
+    pub fn change_direction(&self, velocity: &mut Velocity, walk_speed: f32, rng: &mut impl Rng) {
         let angle = rng.random_range(0.0..std::f32::consts::PI * 2.0);
 
         // 50% chance to stop
@@ -91,7 +93,7 @@ impl Enemy {
         distance_squared <= aggro_range * aggro_range
     }
 
-    pub fn handle_enemy_animation(&mut self, velocity: &Velocity, sprite: &mut Sprite) {
+    pub fn handle_enemy_animation(&mut self, velocity: &Velocity, sprite: &mut Sprite, dt: f32) {
         if let Some(ref mut anim) = sprite.animation {
             if matches!(self.ai_type, AIType::Dead) {
                 if !self.death_animation_finished {
@@ -99,7 +101,7 @@ impl Enemy {
 
                     if anim.is_last_frame() {
                         anim.set_frame(4); // reset to first frame of death animation
-                        anim.update();
+                        advance_animation(anim, sprite.sheet.as_ref(), &mut sprite.frame_timer, dt);
                         self.death_animation_finished = true;
                         anim.playing = false;
                     }
@@ -126,7 +128,7 @@ impl Enemy {
                 };
                 sprite.last_animation = animation_index;
                 anim.set_animation(animation_index);
-                anim.update();
+                advance_animation(anim, sprite.sheet.as_ref(), &mut sprite.frame_timer, dt);
             } else {
                 let idle_animation = match sprite.last_animation {
                     4 => 1, // right -> idle_right
@@ -134,7 +136,7 @@ impl Enemy {
                     _ => 0, // default idle
                 };
                 anim.set_animation(idle_animation);
-                anim.update();
+                advance_animation(anim, sprite.sheet.as_ref(), &mut sprite.frame_timer, dt);
             }
 
             if !matches!(self.ai_type, AIType::Dead) && self.attacking {
@@ -144,160 +146,81 @@ impl Enemy {
                     5 => 8,
                     _ => 0,
                 });
-                anim.update();
+                advance_animation(anim, sprite.sheet.as_ref(), &mut sprite.frame_timer, dt);
             }
         }
     }
 }
 
-pub fn animated_skeleton() -> Option<AnimatedSprite> {
-    Some(AnimatedSprite::new(
-        48,
-        48,
-        &[
-            Animation {
-                name: "idle".to_string(),
-                row: 0,
-                frames: 6,
-                fps: 4,
-            },
-            Animation {
-                name: "idle_sides".to_string(),
-                row: 1,
-                frames: 6,
-                fps: 4,
-            },
-            Animation {
-                name: "idle_up".to_string(),
-                row: 2,
-                frames: 6,
-                fps: 4,
-            },
-            Animation {
-                name: "move_down".to_string(),
-                row: 3,
-                frames: 6,
-                fps: 4,
-            },
-            Animation {
-                name: "move_sides".to_string(),
-                row: 4,
-                frames: 6,
-                fps: 4,
-            },
-            Animation {
-                name: "move_up".to_string(),
-                row: 5,
-                frames: 6,
-                fps: 4,
-            },
-            Animation {
-                name: "attack_down".to_string(),
-                row: 6,
-                frames: 6,
-                fps: 4,
-            },
-            Animation {
-                name: "attack_sides".to_string(),
-                row: 7,
-                frames: 6,
-                fps: 4,
-            },
-            Animation {
-                name: "attack_up".to_string(),
-                row: 8,
-                frames: 6,
-                fps: 4,
-            },
-            Animation {
-                name: "damaged_down".to_string(),
-                row: 9,
-                frames: 4,
-                fps: 4,
-            },
-            Animation {
-                name: "death".to_string(),
-                row: 12,
-                frames: 5,
-                fps: 8,
-            },
-        ],
-        true,
-    ))
+/// Animations every enemy sheet needs, in this order, since
+/// `handle_enemy_animation` switches between them by index.
+pub const ENEMY_ANIMATIONS: [&str; 11] = [
+    "idle",
+    "idle_sides",
+    "idle_up",
+    "move_down",
+    "move_sides",
+    "move_up",
+    "attack_down",
+    "attack_sides",
+    "attack_up",
+    "damaged_down",
+    "death",
+];
+
+/// One kind of enemy, loaded from `assets/enemies/<name>.json` at startup.
+#[derive(Debug, Clone, SerJson, DeJson)]
+pub struct EnemyDefinition {
+    pub texture: String, // asset path, e.g. "images/content/slime.png"
+    pub sprite_size: [f32; 2],
+    pub sheet: SpriteSheet,
+    pub collider: ColliderData,
+    pub health: i32,
+
+    pub walk_speed: f32,
+    pub chase_speed: f32,
+    pub attack_speed: f32,
+    pub attack_interval: f32,
+    pub change_direction_interval: f32,
+    pub aggro_range: f32,
+    pub attack_range: f32,
+    pub attack_animation_duration: f32,
 }
 
-pub fn animated_slime() -> Option<AnimatedSprite> {
-    Some(AnimatedSprite::new(
-        32,
-        32,
-        &[
-            Animation {
-                name: "idle".to_string(),
-                row: 0,
-                frames: 4,
-                fps: 4,
-            },
-            Animation {
-                name: "idle_sides".to_string(),
-                row: 1,
-                frames: 4,
-                fps: 4,
-            },
-            Animation {
-                name: "idle_up".to_string(),
-                row: 2,
-                frames: 4,
-                fps: 4,
-            },
-            Animation {
-                name: "move_down".to_string(),
-                row: 3,
-                frames: 4,
-                fps: 4,
-            },
-            Animation {
-                name: "move_sides".to_string(),
-                row: 4,
-                frames: 4,
-                fps: 4,
-            },
-            Animation {
-                name: "move_up".to_string(),
-                row: 5,
-                frames: 4,
-                fps: 4,
-            },
-            Animation {
-                name: "attack_down".to_string(),
-                row: 6,
-                frames: 7,
-                fps: 4,
-            },
-            Animation {
-                name: "attack_sides".to_string(),
-                row: 7,
-                frames: 7,
-                fps: 4,
-            },
-            Animation {
-                name: "attack_up".to_string(),
-                row: 8,
-                frames: 7,
-                fps: 4,
-            },
-            Animation {
-                name: "damaged_down".to_string(),
-                row: 9,
-                frames: 4,
-                fps: 4,
-            },
-            Animation {
-                name: "death".to_string(),
-                row: 12,
-                frames: 5,
-                fps: 8,
-            },
-        ],
-        true,
-    ))
+impl EnemyDefinition {
+    pub fn validate(&self) -> Result<(), String> {
+        let names: Vec<&str> = self
+            .sheet
+            .animations
+            .iter()
+            .map(|animation| animation.name.as_str())
+            .collect();
+
+        if names != ENEMY_ANIMATIONS {
+            return Err(format!(
+                "animations must be {:?}, got {:?}",
+                ENEMY_ANIMATIONS, names
+            ));
+        }
+        if self.health <= 0 {
+            return Err("health must be positive".to_string());
+        }
+        Ok(())
+    }
+
+    pub fn enemy(&self) -> Enemy {
+        Enemy {
+            walk_speed: self.walk_speed,
+            chase_speed: self.chase_speed,
+            attack_speed: self.attack_speed,
+            attack_interval: self.attack_interval,
+            attack_timer: self.attack_interval,
+            change_direction_interval: self.change_direction_interval,
+            aggro_range: self.aggro_range,
+            attack_range: self.attack_range,
+            attack_animation_timer: self.attack_animation_duration,
+            attack_animation_duration: self.attack_animation_duration,
+            ..Default::default()
+        }
+    }
 }
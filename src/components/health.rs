@@ -0,0 +1,19 @@
+use nanoserde::{DeJson, SerJson};
+
+/// Hit points, kept and saved with the entity. Combat doesn't use them yet,
+/// one hit still kills.
+#[derive(Debug, Clone, SerJson, DeJson)]
+pub struct Health {
+    pub current: i32,
+    pub max: i32,
+}
+
+impl Health {
+    pub fn new(max: i32) -> Self {
+        Self { current: max, max }
+    }
+
+    pub fn is_dead(&self) -> bool {
+        self.current <= 0
+    }
+}
@@ -0,0 +1,9 @@
+use crate::entity::entity::Entity;
+
+/// The entity this one is attached to. Managed by `World::set_parent`, don't
+/// insert it by hand or the parent's `Children` won't know.
+pub struct Parent(pub Entity);
+
+/// Entities attached to this one, in the order they were attached.
+#[derive(Default)]
+pub struct Children(pub Vec<Entity>);
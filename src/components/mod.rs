@@ -1,7 +1,12 @@
 pub mod collider;
+pub mod contact;
 pub mod direction;
 pub mod enemy;
+pub mod health;
+pub mod hierarchy;
+pub mod name;
 pub mod player;
 pub mod position;
+pub mod rng;
 pub mod sprite;
 pub mod velocity;
@@ -0,0 +1,17 @@
+use nanoserde::{DeJson, SerJson};
+
+/// Human readable name, indexed by the `World` so `find_by_name` doesn't have
+/// to scan. Rename by inserting a new `Name` rather than editing this one in
+/// place, or the index won't notice.
+#[derive(Debug, Clone, PartialEq, Eq, SerJson, DeJson)]
+pub struct Name(pub String);
+
+impl Name {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self(name.into())
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
@@ -1,5 +1,11 @@
-use crate::components::{direction::Direction, sprite::Sprite, velocity::Velocity};
+use crate::components::{
+    direction::Direction,
+    sprite::{advance_animation, Sprite},
+    velocity::Velocity,
+};
+use nanoserde::{DeJson, SerJson};
 
+#[derive(Debug, Clone, SerJson, DeJson)]
 pub struct Player {
     pub walk_speed: f32,
     pub attacking: bool,
@@ -30,6 +36,7 @@ impl Player {
         velocity: &Velocity,
         sprite: &mut Sprite,
         player: &Player,
+        dt: f32,
     ) {
         if let Some(ref mut anim) = sprite.animation {
             // movement
@@ -73,7 +80,7 @@ impl Player {
                 });
             }
 
-            anim.update();
+            advance_animation(anim, sprite.sheet.as_ref(), &mut sprite.frame_timer, dt);
         }
     }
 }
@@ -1,4 +1,36 @@
+use macroquad::math::Vec2;
+use nanoserde::{DeJson, SerJson};
+
+#[derive(Debug, Clone, SerJson, DeJson)]
 pub struct Position {
     pub x: f32,
     pub y: f32,
 }
+
+/// Offset from the parent's `Position`. Entities with a `Parent` and this get
+/// their `Position` written by `transform_propagate_system`.
+#[derive(Debug, Default, Clone, SerJson, DeJson)]
+pub struct LocalPosition {
+    pub x: f32,
+    pub y: f32,
+}
+
+/// `Position` before the last fixed update step, so rendering can blend
+/// between the two when frames fall between steps.
+#[derive(Debug, Default, Clone, SerJson, DeJson)]
+pub struct PreviousPosition {
+    pub x: f32,
+    pub y: f32,
+}
+
+impl Position {
+    /// Where to draw the entity, `alpha` of the way from the previous step
+    /// to this one. Entities without a previous position are drawn as is.
+    pub fn interpolated(&self, previous: Option<&PreviousPosition>, alpha: f32) -> Vec2 {
+        let current = Vec2::new(self.x, self.y);
+        match previous {
+            Some(previous) => Vec2::new(previous.x, previous.y).lerp(current, alpha),
+            None => current,
+        }
+    }
+}
@@ -0,0 +1,59 @@
+use rand::{RngCore, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+/// An entity's own random numbers, split off the world seed with a key that
+/// only depends on the entity (see `GameRng::stream`). Other entities coming
+/// and going don't change what this one rolls.
+#[derive(Debug, Clone)]
+pub struct RngStream {
+    pub seed: u64,
+    pub key: u64,
+    rng: ChaCha8Rng,
+}
+
+impl RngStream {
+    pub fn new(seed: u64, key: u64) -> Self {
+        let mut rng = ChaCha8Rng::seed_from_u64(seed);
+        rng.set_stream(key);
+        Self { seed, key, rng }
+    }
+
+    /// How many words have been drawn, for save files.
+    pub fn position(&self) -> u128 {
+        self.rng.get_word_pos()
+    }
+
+    pub fn set_position(&mut self, position: u128) {
+        self.rng.set_word_pos(position);
+    }
+}
+
+impl RngCore for RngStream {
+    fn next_u32(&mut self) -> u32 {
+        self.rng.next_u32()
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.rng.next_u64()
+    }
+
+    fn fill_bytes(&mut self, dst: &mut [u8]) {
+        self.rng.fill_bytes(dst)
+    }
+}
+
+/// Stable key out of anything hashable into bytes (FNV-1a). `std`'s hasher
+/// isn't guaranteed to stay the same between releases, this is.
+pub fn stream_key(parts: &[&[u8]]) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for part in parts {
+        for byte in *part {
+            hash ^= *byte as u64;
+            hash = hash.wrapping_mul(0x0000_0100_0000_01b3);
+        }
+        // separator, so ["ab", "c"] and ["a", "bc"] differ
+        hash ^= 0xff;
+        hash = hash.wrapping_mul(0x0000_0100_0000_01b3);
+    }
+    hash
+}
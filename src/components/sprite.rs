@@ -1,14 +1,77 @@
 use macroquad::{
     math::{Rect, Vec2},
-    prelude::animation::AnimatedSprite,
+    prelude::animation::{AnimatedSprite, Animation},
     texture::Texture2D,
 };
+use nanoserde::{DeJson, SerJson};
 
 pub struct Sprite {
     pub texture: Texture2D,
     pub source_rect: Option<Rect>, // none will render the entire sprite sheet
     pub animation: Option<AnimatedSprite>, // animated or static sprite
+    pub sheet: Option<SpriteSheet>, // what animation was built from, for saving
     pub dest_size: Option<Vec2>,
     pub flipped: bool,
     pub last_animation: usize,
+    pub frame_timer: f32, // time on the current frame, see `advance_animation`
+}
+
+/// Layout of an animated sprite sheet: one animation per row.
+#[derive(Debug, Clone, SerJson, DeJson)]
+pub struct SpriteSheet {
+    pub frame_width: u32,
+    pub frame_height: u32,
+    pub animations: Vec<AnimationDef>,
+}
+
+/// Same as macroquad's `Animation`, which can't be serialized.
+#[derive(Debug, Clone, SerJson, DeJson)]
+pub struct AnimationDef {
+    pub name: String,
+    pub row: u32,
+    pub frames: u32,
+    pub fps: u32,
+}
+
+impl SpriteSheet {
+    pub fn animated_sprite(&self) -> AnimatedSprite {
+        let animations: Vec<Animation> = self
+            .animations
+            .iter()
+            .map(|animation| Animation {
+                name: animation.name.clone(),
+                row: animation.row,
+                frames: animation.frames,
+                fps: animation.fps,
+            })
+            .collect();
+
+        AnimatedSprite::new(self.frame_width, self.frame_height, &animations, true)
+    }
+}
+
+/// Same as `AnimatedSprite::update`, with the frame time passed in instead of
+/// read from macroquad so animations also run headless. The fps comes from
+/// the sheet, sprites without one don't advance.
+pub fn advance_animation(
+    animation: &mut AnimatedSprite,
+    sheet: Option<&SpriteSheet>,
+    timer: &mut f32,
+    dt: f32,
+) {
+    let Some(def) = sheet.and_then(|sheet| sheet.animations.get(animation.current_animation()))
+    else {
+        return;
+    };
+
+    let source = animation.frame().source_rect;
+    let mut frame = (source.x / source.w) as u32;
+    if animation.playing {
+        *timer += dt;
+        if *timer > 1. / def.fps as f32 {
+            frame += 1;
+            *timer = 0.0;
+        }
+    }
+    animation.set_frame(frame % def.frames);
 }
@@ -1,4 +1,6 @@
-#[derive(Default)]
+use nanoserde::{DeJson, SerJson};
+
+#[derive(Debug, Default, Clone, SerJson, DeJson)]
 pub struct Velocity {
     pub x: f32,
     pub y: f32,
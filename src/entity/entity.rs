@@ -1 +1,103 @@
-pub type Entity = usize;
+/// Handle to an entity in the `World`.
+///
+/// The `generation` is bumped every time a slot is despawned, so a handle
+/// kept around after its entity died won't resolve to whatever reuses the slot.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Entity {
+    pub index: usize,
+    pub generation: u32,
+}
+
+struct EntitySlot {
+    generation: u32,
+    alive: bool,
+}
+
+/// Allocates entity handles and recycles despawned slots through a free list.
+#[derive(Default)]
+pub struct Entities {
+    slots: Vec<EntitySlot>,
+    free: Vec<usize>,
+}
+
+impl Entities {
+    /// Returns the new entity and whether a brand new slot had to be created.
+    pub fn alloc(&mut self) -> (Entity, bool) {
+        if let Some(index) = self.free.pop() {
+            let slot = &mut self.slots[index];
+            slot.alive = true;
+            return (
+                Entity {
+                    index,
+                    generation: slot.generation,
+                },
+                false,
+            );
+        }
+
+        let index = self.slots.len();
+        self.slots.push(EntitySlot {
+            generation: 0,
+            alive: true,
+        });
+        (
+            Entity {
+                index,
+                generation: 0,
+            },
+            true,
+        )
+    }
+
+    /// Marks the slot as free. Returns false if the handle was already stale.
+    pub fn free(&mut self, entity: Entity) -> bool {
+        if !self.is_alive(entity) {
+            return false;
+        }
+
+        let slot = &mut self.slots[entity.index];
+        slot.alive = false;
+        slot.generation = slot.generation.wrapping_add(1);
+        self.free.push(entity.index);
+        true
+    }
+
+    pub fn is_alive(&self, entity: Entity) -> bool {
+        self.slots
+            .get(entity.index)
+            .is_some_and(|slot| slot.alive && slot.generation == entity.generation)
+    }
+
+    /// The live entity in a slot, if any.
+    pub fn get(&self, index: usize) -> Option<Entity> {
+        let slot = self.slots.get(index)?;
+        slot.alive.then_some(Entity {
+            index,
+            generation: slot.generation,
+        })
+    }
+
+    /// Number of slots, alive or not. Component vecs are sized to this.
+    pub fn len(&self) -> usize {
+        self.slots.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.slots.is_empty()
+    }
+
+    pub fn alive_count(&self) -> usize {
+        self.slots.len() - self.free.len()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = Entity> + '_ {
+        self.slots
+            .iter()
+            .enumerate()
+            .filter(|(_, slot)| slot.alive)
+            .map(|(index, slot)| Entity {
+                index,
+                generation: slot.generation,
+            })
+    }
+}
@@ -0,0 +1,98 @@
+use macroquad::math::Vec2;
+
+use crate::entity::entity::Entity;
+
+/// Double-buffered queue of events of one type, stored as a world resource.
+///
+/// Events sent during a frame become readable once `World::update_events` swaps
+/// the buffers at the end of it, and stay readable for the whole next frame.
+/// That way every reader sees each event exactly once, no matter if it runs
+/// before or after the writer.
+///
+/// FixedUpdate systems can send events but not read them. The stage runs a
+/// varying number of times per frame, zero included, so a reader there would
+/// see an event several times or never. `event_reader` panics there.
+pub struct Events<T> {
+    sending: Vec<T>,
+    readable: Vec<T>,
+}
+
+impl<T> Default for Events<T> {
+    fn default() -> Self {
+        Self {
+            sending: Vec::new(),
+            readable: Vec::new(),
+        }
+    }
+}
+
+impl<T> Events<T> {
+    pub fn send(&mut self, event: T) {
+        self.sending.push(event);
+    }
+
+    /// Drops last frame's events and makes this frame's readable.
+    pub fn update(&mut self) {
+        self.readable.clear();
+        std::mem::swap(&mut self.sending, &mut self.readable);
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, T> {
+        self.readable.iter()
+    }
+}
+
+/// Sends events of type `T`, get one with `World::event_writer`.
+pub struct EventWriter<'a, T> {
+    events: &'a mut Events<T>,
+}
+
+impl<'a, T> EventWriter<'a, T> {
+    pub fn new(events: &'a mut Events<T>) -> Self {
+        Self { events }
+    }
+
+    pub fn send(&mut self, event: T) {
+        self.events.send(event);
+    }
+}
+
+/// Reads the events of type `T` sent last frame, get one with
+/// `World::event_reader`.
+pub struct EventReader<'a, T> {
+    events: &'a Events<T>,
+}
+
+impl<'a, T> EventReader<'a, T> {
+    pub fn new(events: &'a Events<T>) -> Self {
+        Self { events }
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'a, T> {
+        self.events.readable.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.events.readable.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.events.readable.is_empty()
+    }
+}
+
+// game events
+
+/// An attacking enemy hit the player.
+#[derive(Debug, Clone, Copy)]
+pub struct PlayerHit {
+    pub player: Entity,
+    pub enemy: Entity,
+}
+
+/// The player killed an enemy.
+#[derive(Debug, Clone, Copy)]
+pub struct EnemyKilled {
+    pub enemy: Entity,
+    pub position: Vec2,
+}
@@ -0,0 +1,248 @@
+use std::marker::PhantomData;
+
+use crate::{
+    query::{AccessList, ComponentAccess},
+    storage::ColumnPtr,
+    world::World,
+};
+
+/// Narrows down which entities a query visits without borrowing anything,
+/// e.g. `world.query_filtered::<&Position, With<Player>>()`.
+/// Tuples of filters must all match.
+pub trait QueryFilter<'a> {
+    type State: Copy;
+
+    /// Components the filter looks at, only read. Used to check a parallel
+    /// system's declared access.
+    fn access(access: &mut AccessList);
+
+    /// Returns `None` if nothing can match. `Changed`/`Added` match what
+    /// happened after the `last_run` change tick.
+    ///
+    /// # Safety
+    /// Nothing may borrow what the filter looks at mutably while it's used.
+    unsafe fn prepare(world: &'a World, last_run: u32) -> Option<Self::State>;
+
+    /// See `Fetch::candidates`.
+    ///
+    /// # Safety
+    /// `state` must come from `prepare` and the world must be unchanged since.
+    unsafe fn candidates(state: Self::State) -> Option<&'a [usize]>;
+
+    /// # Safety
+    /// Same as `candidates`.
+    unsafe fn matches(state: Self::State, index: usize) -> bool;
+}
+
+/// Entities that have a `T`, without borrowing it.
+pub struct With<T>(PhantomData<T>);
+
+/// Entities that don't have a `T`.
+pub struct Without<T>(PhantomData<T>);
+
+/// Entities whose `T` was borrowed mutably since the system last ran, so a
+/// system sees every change once, even ones made after it in the previous
+/// frame. Outside the schedule it's since the last `clear_trackers`.
+/// Mutable queries count as a change even if nothing was written.
+pub struct Changed<T>(PhantomData<T>);
+
+/// Entities that got a `T` since the system last ran, same as `Changed`.
+pub struct Added<T>(PhantomData<T>);
+
+impl<'a> QueryFilter<'a> for () {
+    type State = ();
+
+    fn access(_access: &mut AccessList) {}
+
+    unsafe fn prepare(_world: &'a World, _last_run: u32) -> Option<Self::State> {
+        Some(())
+    }
+
+    unsafe fn candidates(_state: Self::State) -> Option<&'a [usize]> {
+        None
+    }
+
+    unsafe fn matches(_state: Self::State, _index: usize) -> bool {
+        true
+    }
+}
+
+impl<'a, T: 'static> QueryFilter<'a> for With<T> {
+    type State = ColumnPtr<T>;
+
+    fn access(access: &mut AccessList) {
+        access.push(ComponentAccess::of::<T>(false));
+    }
+
+    unsafe fn prepare(world: &'a World, _last_run: u32) -> Option<Self::State> {
+        world.column_ptr::<T>()
+    }
+
+    unsafe fn candidates(state: Self::State) -> Option<&'a [usize]> {
+        unsafe { state.entities() }
+    }
+
+    unsafe fn matches(state: Self::State, index: usize) -> bool {
+        unsafe { state.contains(index) }
+    }
+}
+
+impl<'a, T: 'static> QueryFilter<'a> for Without<T> {
+    type State = Option<ColumnPtr<T>>;
+
+    fn access(access: &mut AccessList) {
+        access.push(ComponentAccess::of::<T>(false));
+    }
+
+    unsafe fn prepare(world: &'a World, _last_run: u32) -> Option<Self::State> {
+        Some(world.column_ptr::<T>())
+    }
+
+    unsafe fn candidates(_state: Self::State) -> Option<&'a [usize]> {
+        None
+    }
+
+    unsafe fn matches(state: Self::State, index: usize) -> bool {
+        state.is_none_or(|column| unsafe { !column.contains(index) })
+    }
+}
+
+impl<'a, T: 'static> QueryFilter<'a> for Changed<T> {
+    /// The column and the tick to compare against.
+    type State = (ColumnPtr<T>, u32);
+
+    fn access(access: &mut AccessList) {
+        access.push(ComponentAccess::of::<T>(false));
+    }
+
+    unsafe fn prepare(world: &'a World, last_run: u32) -> Option<Self::State> {
+        Some((world.column_ptr::<T>()?, last_run))
+    }
+
+    unsafe fn candidates((column, _): Self::State) -> Option<&'a [usize]> {
+        unsafe { column.entities() }
+    }
+
+    unsafe fn matches((column, last_run): Self::State, index: usize) -> bool {
+        unsafe {
+            column
+                .ticks(index)
+                .is_some_and(|ticks| ticks.is_changed(last_run, column.change_tick()))
+        }
+    }
+}
+
+impl<'a, T: 'static> QueryFilter<'a> for Added<T> {
+    /// The column and the tick to compare against.
+    type State = (ColumnPtr<T>, u32);
+
+    fn access(access: &mut AccessList) {
+        access.push(ComponentAccess::of::<T>(false));
+    }
+
+    unsafe fn prepare(world: &'a World, last_run: u32) -> Option<Self::State> {
+        Some((world.column_ptr::<T>()?, last_run))
+    }
+
+    unsafe fn candidates((column, _): Self::State) -> Option<&'a [usize]> {
+        unsafe { column.entities() }
+    }
+
+    unsafe fn matches((column, last_run): Self::State, index: usize) -> bool {
+        unsafe {
+            column
+                .ticks(index)
+                .is_some_and(|ticks| ticks.is_added(last_run, column.change_tick()))
+        }
+    }
+}
+
+macro_rules! impl_filter_for_tuple {
+    ($($name:ident),+) => {
+        #[allow(non_snake_case)]
+        impl<'a, $($name: QueryFilter<'a>),+> QueryFilter<'a> for ($($name,)+) {
+            type State = ($($name::State,)+);
+
+            fn access(access: &mut AccessList) {
+                $($name::access(access);)+
+            }
+
+            unsafe fn prepare(world: &'a World, last_run: u32) -> Option<Self::State> {
+                unsafe { Some(($($name::prepare(world, last_run)?,)+)) }
+            }
+
+            unsafe fn candidates(state: Self::State) -> Option<&'a [usize]> {
+                let ($($name,)+) = state;
+                let mut smallest: Option<&'a [usize]> = None;
+                $(
+                    if let Some(candidates) = unsafe { $name::candidates($name) } {
+                        if smallest.is_none_or(|smallest| candidates.len() < smallest.len()) {
+                            smallest = Some(candidates);
+                        }
+                    }
+                )+
+                smallest
+            }
+
+            unsafe fn matches(state: Self::State, index: usize) -> bool {
+                let ($($name,)+) = state;
+                unsafe { $($name::matches($name, index))&&+ }
+            }
+        }
+    };
+}
+
+impl_filter_for_tuple!(A);
+impl_filter_for_tuple!(A, B);
+impl_filter_for_tuple!(A, B, C);
+impl_filter_for_tuple!(A, B, C, D);
+
+#[cfg(test)]
+mod tests {
+    use super::{Added, Changed, With, Without};
+    use crate::{entity::entity::Entity, world::World};
+
+    struct Position(f32);
+    struct Player;
+
+    #[test]
+    fn with_and_without() {
+        let mut world = World::new();
+        let player = world.spawn_entity().with(Position(0.0)).with(Player).id();
+        let other = world.spawn_entity().with(Position(0.0)).id();
+
+        let with: Vec<_> = world.query_filtered::<Entity, With<Player>>().collect();
+        assert_eq!(with, vec![player]);
+
+        let without: Vec<_> = world
+            .query_filtered::<Entity, (With<Position>, Without<Player>)>()
+            .collect();
+        assert_eq!(without, vec![other]);
+    }
+
+    #[test]
+    fn changed_and_added_since_clear_trackers() {
+        let mut world = World::new();
+        world.spawn_entity().with(Position(0.0));
+        let moved = world.spawn_entity().with(Position(0.0)).id();
+        world.clear_trackers();
+
+        let new = world.spawn_entity().with(Position(0.0)).id();
+        world.get_component_mut::<Position>(moved).unwrap().0 = 1.0;
+
+        let added: Vec<_> = world.query_filtered::<Entity, Added<Position>>().collect();
+        assert_eq!(added, vec![new]);
+
+        // inserting counts as a change too
+        let changed: Vec<_> = world
+            .query_filtered::<Entity, Changed<Position>>()
+            .collect();
+        assert_eq!(changed, vec![moved, new]);
+
+        world.clear_trackers();
+        assert_eq!(
+            world.query_filtered::<Entity, Changed<Position>>().count(),
+            0
+        );
+    }
+}
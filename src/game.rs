@@ -0,0 +1,138 @@
+use crate::{
+    components::{
+        collider::Collider,
+        contact::Contact,
+        enemy::Enemy,
+        health::Health,
+        name::Name,
+        player::Player,
+        position::{LocalPosition, Position, PreviousPosition},
+        rng::RngStream,
+        sprite::Sprite,
+        velocity::Velocity,
+    },
+    events::{EnemyKilled, Events, PlayerHit},
+    resources::{GameRng, InputState, Time},
+    schedule::{Schedule, Stage},
+    spatial::{spatial_index_system, SpatialIndex},
+    storage::StorageType,
+    systems::systems::{
+        animation_systems, camera_systems, clear_screen_system, enemy_aggro_system,
+        enemy_movement_systems, foreground_render_system, hit_systems, input_systems,
+        movement_systems, player_attack_system, render_systems, save_load_system,
+        store_previous_position_system, tilemap_render_system, transform_propagate_system,
+    },
+    world::World,
+};
+
+/// Storage, events and save keys, everything the world needs before the
+/// resources are loaded.
+pub fn setup_world(world: &mut World) {
+    // only a handful of entities have these, no need for a slot per tile object
+    world.register_component::<Player>(StorageType::SparseSet);
+    world.register_component::<Enemy>(StorageType::SparseSet);
+    world.register_component::<RngStream>(StorageType::SparseSet);
+
+    world.add_event::<PlayerHit>();
+    world.add_event::<EnemyKilled>();
+
+    // what F5 writes to the save file, keys must not change between versions
+    world.register_persistent::<Position>("position");
+    world.register_persistent::<LocalPosition>("local_position");
+    world.register_persistent::<PreviousPosition>("previous_position");
+    world.register_persistent::<Velocity>("velocity");
+    world.register_persistent::<Collider>("collider");
+    world.register_persistent::<Sprite>("sprite");
+    world.register_persistent::<Player>("player");
+    world.register_persistent::<Enemy>("enemy");
+    world.register_persistent::<Health>("health");
+    world.register_persistent::<Name>("name");
+    world.register_persistent::<RngStream>("rng_stream");
+    world.register_persistent::<Contact>("contact");
+}
+
+/// The game's systems, without anything that talks to the window. Whoever
+/// runs it fills `Time` and `InputState` in PreUpdate (before
+/// `save_load_system`) and presents the `DrawQueue` afterwards.
+pub fn schedule() -> Schedule {
+    let mut schedule = Schedule::new();
+
+    schedule.add_system(Stage::PreUpdate, save_load_system);
+
+    // gameplay runs at a fixed rate, see `FixedTime`
+    schedule
+        .add_parallel_system(Stage::FixedUpdate, store_previous_position_system)
+        .before(movement_systems)
+        .access::<(&Position, &mut PreviousPosition)>();
+    schedule
+        .add_parallel_system(Stage::FixedUpdate, spatial_index_system)
+        .before(player_attack_system)
+        .before(hit_systems)
+        .access::<(&Position, &Collider)>()
+        .resource_mut::<SpatialIndex>();
+    schedule
+        .add_parallel_system(Stage::FixedUpdate, input_systems)
+        .access::<(&mut Velocity, &mut Player)>()
+        .resource::<InputState>()
+        .resource::<Time>();
+    schedule
+        .add_parallel_system(Stage::FixedUpdate, enemy_aggro_system)
+        .access::<(&Position, &Player, &mut Enemy)>();
+    schedule
+        .add_parallel_system(Stage::FixedUpdate, player_attack_system)
+        .after(input_systems)
+        .access::<(&Position, &Collider, &Player, &mut Enemy)>()
+        .resource::<Time>()
+        .resource::<SpatialIndex>()
+        .resource_mut::<Events<EnemyKilled>>();
+    schedule
+        .add_parallel_system(Stage::FixedUpdate, enemy_movement_systems)
+        .after(enemy_aggro_system)
+        .access::<(
+            &Position,
+            &Player,
+            &mut Velocity,
+            &mut Enemy,
+            &mut RngStream,
+            &Contact,
+        )>()
+        .resource::<Time>()
+        .resource_mut::<GameRng>();
+    schedule
+        .add_parallel_system(Stage::FixedUpdate, hit_systems)
+        .access::<(&Position, &Enemy, &mut Player)>()
+        .resource::<Time>()
+        .resource::<SpatialIndex>()
+        .resource_mut::<Events<PlayerHit>>();
+    schedule
+        .add_parallel_system(Stage::FixedUpdate, movement_systems)
+        .after(input_systems)
+        .after(enemy_movement_systems)
+        .access::<(&mut Position, &Velocity, &Collider, &mut Contact)>()
+        .resource::<Time>()
+        .resource_mut::<SpatialIndex>();
+
+    // animations advance with the frame time, once per frame
+    schedule
+        .add_parallel_system(Stage::Update, animation_systems)
+        .access::<(&mut Sprite, &Velocity, &Player, &mut Enemy)>()
+        .resource::<Time>();
+
+    schedule.add_system(Stage::PostUpdate, transform_propagate_system);
+    schedule
+        .add_system(Stage::PostUpdate, camera_systems)
+        .after(transform_propagate_system);
+
+    schedule.add_system(Stage::Render, clear_screen_system);
+    schedule
+        .add_system(Stage::Render, tilemap_render_system)
+        .after(clear_screen_system);
+    schedule
+        .add_system(Stage::Render, render_systems)
+        .after(tilemap_render_system);
+    schedule
+        .add_system(Stage::Render, foreground_render_system)
+        .after(render_systems);
+
+    schedule
+}
@@ -0,0 +1,73 @@
+use std::error::Error;
+
+use crate::{
+    game,
+    render::{DrawCommand, DrawQueue},
+    replay::Replay,
+    resources::{self, FixedTime, InputState, Time},
+    save::SaveSlot,
+    schedule::Schedule,
+    world::World,
+};
+
+/// The game without a window: placeholder textures, no drawing, and time and
+/// input are whatever the caller sets. Saves stay in memory. For tests and
+/// CI.
+///
+/// Enemy definitions are still read from the crate's `assets/enemies` on
+/// disk, see `load_enemies`.
+pub struct HeadlessGame {
+    pub world: World,
+    pub schedule: Schedule,
+    /// What the last frame would have drawn.
+    pub draws: Vec<DrawCommand>,
+}
+
+impl HeadlessGame {
+    /// Same seed and same inputs give the same run.
+    pub fn new(seed: u64) -> Result<Self, Box<dyn Error>> {
+        let mut world = World::new();
+        game::setup_world(&mut world);
+        resources::load_headless(&mut world, seed)?;
+
+        Ok(Self {
+            world,
+            schedule: game::schedule(),
+            draws: Vec::new(),
+        })
+    }
+
+    /// A new game that has played `replay` to the end.
+    pub fn play(replay: &Replay) -> Result<Self, Box<dyn Error>> {
+        let mut game = Self::new(replay.seed)?;
+        game.world
+            .insert_resource(SaveSlot::Memory(replay.save.clone()));
+        if let Some(max_steps) = replay.max_steps {
+            game.world.resource_mut::<FixedTime>().max_steps = max_steps;
+        }
+        for frame in &replay.frames {
+            *game.input_mut() = frame.input;
+            game.step(frame.delta);
+        }
+        Ok(game)
+    }
+
+    /// Input used from the next frame on, until changed.
+    pub fn input_mut(&mut self) -> &mut InputState {
+        self.world.resource_mut::<InputState>()
+    }
+
+    /// Runs one frame of `dt` seconds.
+    pub fn step(&mut self, dt: f32) {
+        self.world.resource_mut::<Time>().advance(dt);
+        self.schedule.run(&mut self.world);
+        self.draws = self.world.resource_mut::<DrawQueue>().take();
+    }
+
+    /// Runs `frames` frames of `dt` seconds each.
+    pub fn run(&mut self, frames: usize, dt: f32) {
+        for _ in 0..frames {
+            self.step(dt);
+        }
+    }
+}
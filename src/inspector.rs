@@ -0,0 +1,305 @@
+use macroquad::{
+    camera::Camera2D,
+    color::{GREEN, YELLOW},
+    hash,
+    input::{is_mouse_button_pressed, mouse_position, MouseButton},
+    math::{vec2, Rect, Vec2},
+    ui::{root_ui, widgets, Id, Ui},
+};
+
+use crate::{
+    components::{
+        collider::Collider,
+        direction::Direction,
+        enemy::{AIType, Enemy},
+        health::Health,
+        name::Name,
+        player::Player,
+        position::{LocalPosition, Position},
+        sprite::Sprite,
+        velocity::Velocity,
+    },
+    entity::entity::Entity,
+    render::{DrawCommand, DrawQueue},
+    resources::InputState,
+    world::World,
+};
+
+/// Draws a component's fields in the inspector window, returning whether one
+/// was edited. Edits are written back to the component right away, so they
+/// show up on the next frame.
+pub trait Inspect: Clone + 'static {
+    fn inspect(&mut self, ui: &mut Ui, id: Id) -> bool;
+}
+
+type InspectFn = fn(&mut World, Entity, &mut Ui, &'static str);
+
+struct InspectableType {
+    label: &'static str,
+    inspect: InspectFn,
+}
+
+/// Debug overlay state, toggled with F1. Components show up in it once
+/// registered with `register::<T>("Label")`.
+#[derive(Default)]
+pub struct Inspector {
+    pub open: bool,
+    pub selected: Option<Entity>,
+    types: Vec<InspectableType>,
+}
+
+impl Inspector {
+    pub fn register<T: Inspect>(&mut self, label: &'static str) {
+        self.types.push(InspectableType {
+            label,
+            inspect: |world, entity, ui, label| {
+                // widgets work on a copy, so only an actual edit borrows the
+                // component mutably and marks it changed
+                let Some(mut copy) = world.get_component::<T>(entity).cloned() else {
+                    return;
+                };
+                let id = hash!(label, entity.index, entity.generation);
+                let mut edited = false;
+                ui.tree_node(id, label, |ui| edited = copy.inspect(ui, id));
+                if edited && let Some(component) = world.get_component_mut::<T>(entity) {
+                    *component = copy;
+                }
+            },
+        });
+    }
+}
+
+fn drag(ui: &mut Ui, id: Id, label: &str, value: &mut f32) -> bool {
+    let before = *value;
+    ui.drag(hash!(id, label), label, None, value);
+    *value != before
+}
+
+fn drag_i32(ui: &mut Ui, id: Id, label: &str, value: &mut i32) -> bool {
+    let before = *value;
+    let mut as_float = *value as f32;
+    ui.drag(hash!(id, label), label, None, &mut as_float);
+    *value = as_float as i32;
+    *value != before
+}
+
+fn checkbox(ui: &mut Ui, id: Id, label: &str, value: &mut bool) -> bool {
+    let before = *value;
+    ui.checkbox(hash!(id, label), label, value);
+    *value != before
+}
+
+/// The picked variant, `None` if it's still `selected`.
+fn combo(ui: &mut Ui, id: Id, label: &str, variants: &[&str], selected: usize) -> Option<usize> {
+    let mut picked = selected;
+    ui.combo_box(hash!(id, label), label, variants, &mut picked);
+    (picked != selected).then_some(picked)
+}
+
+impl Inspect for Position {
+    fn inspect(&mut self, ui: &mut Ui, id: Id) -> bool {
+        drag(ui, id, "x", &mut self.x) | drag(ui, id, "y", &mut self.y)
+    }
+}
+
+impl Inspect for LocalPosition {
+    fn inspect(&mut self, ui: &mut Ui, id: Id) -> bool {
+        drag(ui, id, "x", &mut self.x) | drag(ui, id, "y", &mut self.y)
+    }
+}
+
+impl Inspect for Velocity {
+    fn inspect(&mut self, ui: &mut Ui, id: Id) -> bool {
+        drag(ui, id, "x", &mut self.x) | drag(ui, id, "y", &mut self.y)
+    }
+}
+
+impl Inspect for Health {
+    fn inspect(&mut self, ui: &mut Ui, id: Id) -> bool {
+        drag_i32(ui, id, "current", &mut self.current) | drag_i32(ui, id, "max", &mut self.max)
+    }
+}
+
+impl Inspect for Collider {
+    fn inspect(&mut self, ui: &mut Ui, id: Id) -> bool {
+        drag(ui, id, "offset x", &mut self.collision_offset.x)
+            | drag(ui, id, "offset y", &mut self.collision_offset.y)
+            | drag(ui, id, "size x", &mut self.collision_size.x)
+            | drag(ui, id, "size y", &mut self.collision_size.y)
+    }
+}
+
+const AI_TYPES: [&str; 4] = ["Attack", "ChasePlayer", "Wander", "Dead"];
+
+impl Inspect for Enemy {
+    fn inspect(&mut self, ui: &mut Ui, id: Id) -> bool {
+        let current = match self.ai_type {
+            AIType::Attack => 0,
+            AIType::ChasePlayer => 1,
+            AIType::Wander => 2,
+            AIType::Dead => 3,
+        };
+        let picked = combo(ui, id, "ai", &AI_TYPES, current);
+        if let Some(picked) = picked {
+            self.ai_type = match picked {
+                0 => AIType::Attack,
+                1 => AIType::ChasePlayer,
+                2 => AIType::Wander,
+                _ => AIType::Dead,
+            };
+        }
+        picked.is_some()
+            | drag(ui, id, "walk speed", &mut self.walk_speed)
+            | drag(ui, id, "chase speed", &mut self.chase_speed)
+            | drag(ui, id, "attack speed", &mut self.attack_speed)
+            | drag(ui, id, "aggro range", &mut self.aggro_range)
+            | drag(ui, id, "attack range", &mut self.attack_range)
+            | drag(ui, id, "movement timer", &mut self.movement_timer)
+            | drag(ui, id, "attack timer", &mut self.attack_timer)
+            | drag(
+                ui,
+                id,
+                "attack anim timer",
+                &mut self.attack_animation_timer,
+            )
+            | drag(ui, id, "hit cooldown", &mut self.hit_cooldown)
+            | checkbox(ui, id, "attacking", &mut self.attacking)
+    }
+}
+
+const DIRECTIONS: [&str; 4] = ["Up", "Down", "Left", "Right"];
+
+impl Inspect for Player {
+    fn inspect(&mut self, ui: &mut Ui, id: Id) -> bool {
+        let current = match self.last_direction {
+            Direction::Up => 0,
+            Direction::Down => 1,
+            Direction::Left => 2,
+            Direction::Right => 3,
+        };
+        let picked = combo(ui, id, "facing", &DIRECTIONS, current);
+        if let Some(picked) = picked {
+            self.last_direction = match picked {
+                0 => Direction::Up,
+                1 => Direction::Down,
+                2 => Direction::Left,
+                _ => Direction::Right,
+            };
+        }
+        picked.is_some()
+            | drag(ui, id, "walk speed", &mut self.walk_speed)
+            | drag(ui, id, "attack timer", &mut self.attack_timer)
+            | drag(ui, id, "hit cooldown", &mut self.hit_cooldown_timer)
+            | checkbox(ui, id, "attacking", &mut self.attacking)
+    }
+}
+
+/// Where an entity is drawn, used for click picking and the outline.
+fn sprite_rect(world: &World, entity: Entity) -> Option<Rect> {
+    let position = world.get_component::<Position>(entity)?;
+    let sprite = world.get_component::<Sprite>(entity)?;
+    let size = sprite
+        .dest_size
+        .or_else(|| {
+            let source = sprite
+                .animation
+                .as_ref()
+                .map(|animation| animation.frame().source_rect)
+                .or(sprite.source_rect)?;
+            Some(vec2(source.w, source.h))
+        })
+        .unwrap_or(vec2(16.0, 16.0));
+
+    Some(Rect::new(position.x, position.y, size.x, size.y))
+}
+
+/// Smallest sprite under the point, so an enemy standing on a decoration
+/// wins over the decoration.
+fn pick(world: &World, point: Vec2) -> Option<Entity> {
+    world
+        .entities()
+        .filter_map(|entity| Some((entity, sprite_rect(world, entity)?)))
+        .filter(|(_, rect)| rect.contains(point))
+        .min_by(|(_, a), (_, b)| (a.w * a.h).total_cmp(&(b.w * b.h)))
+        .map(|(entity, _)| entity)
+}
+
+fn entity_label(world: &World, entity: Entity) -> String {
+    match world.get_component::<Name>(entity) {
+        Some(name) => format!("{} #{}v{}", name.as_str(), entity.index, entity.generation),
+        None => format!("#{}v{}", entity.index, entity.generation),
+    }
+}
+
+/// F1 toggles the overlay. While open, clicking a sprite selects it and its
+/// registered components can be edited in the window. Runs last in Render
+/// so the outlines go on top of the sprites.
+pub fn inspector_system(world: &mut World) {
+    world.resource_scope::<Inspector, _>(|world, inspector| {
+        if world.resource::<InputState>().inspector {
+            inspector.open = !inspector.open;
+        }
+        if !inspector.open {
+            return;
+        }
+        if inspector
+            .selected
+            .is_some_and(|entity| !world.is_alive(entity))
+        {
+            inspector.selected = None;
+        }
+
+        let mut ui = root_ui();
+        let mouse = Vec2::from(mouse_position());
+        if is_mouse_button_pressed(MouseButton::Left) && !ui.is_mouse_over(mouse) {
+            let point = world.resource::<Camera2D>().screen_to_world(mouse);
+            inspector.selected = pick(world, point);
+        }
+
+        let mut outlines = Vec::new();
+        if let Some(rect) = inspector
+            .selected
+            .and_then(|entity| sprite_rect(world, entity))
+        {
+            outlines.push((rect, YELLOW));
+        }
+        if let Some(entity) = inspector.selected
+            && let (Some(position), Some(collider)) = (
+                world.get_component::<Position>(entity),
+                world.get_component::<Collider>(entity),
+            )
+        {
+            let rect = collider.collision_box(vec2(position.x, position.y));
+            outlines.push((rect, GREEN));
+        }
+        let queue = world.resource_mut::<DrawQueue>();
+        for (rect, color) in outlines {
+            queue.push(DrawCommand::RectLines {
+                rect,
+                thickness: 1.0,
+                color,
+            });
+        }
+
+        widgets::Window::new(hash!(), vec2(10.0, 10.0), vec2(340.0, 500.0))
+            .label("Inspector")
+            .ui(&mut ui, |ui| {
+                widgets::Group::new(hash!(), vec2(330.0, 150.0)).ui(ui, |ui| {
+                    for entity in world.entities().collect::<Vec<_>>() {
+                        if ui.button(None, entity_label(world, entity).as_str()) {
+                            inspector.selected = Some(entity);
+                        }
+                    }
+                });
+
+                let Some(entity) = inspector.selected else {
+                    ui.label(None, "click an entity to inspect it");
+                    return;
+                };
+                ui.label(None, &entity_label(world, entity));
+                for inspectable in &inspector.types {
+                    (inspectable.inspect)(world, entity, ui, inspectable.label);
+                }
+            });
+    });
+}
@@ -0,0 +1,22 @@
+pub mod commands;
+pub mod components;
+pub mod entity;
+pub mod events;
+pub mod filter;
+pub mod game;
+pub mod headless;
+pub mod inspector;
+pub mod options;
+pub mod parallel;
+mod pool;
+pub mod query;
+pub mod render;
+pub mod replay;
+pub mod resources;
+pub mod save;
+pub mod schedule;
+pub mod shape;
+pub mod spatial;
+pub mod storage;
+pub mod systems;
+pub mod world;
@@ -2,22 +2,27 @@ use std::error::Error;
 
 use macroquad::prelude::*;
 
-use crate::{
-    resources::Resources,
-    systems::systems::{
-        animation_systems, camera_systems, enemy_aggro_system, enemy_movement_systems, hit_systems,
-        input_systems, movement_systems, player_attack_system, tilemap_render_system,
+use rustling::{
+    components::{
+        collider::Collider,
+        enemy::Enemy,
+        health::Health,
+        player::Player,
+        position::{LocalPosition, Position},
+        velocity::Velocity,
     },
+    game,
+    inspector::{inspector_system, Inspector},
+    options::Options,
+    render::{DrawQueue, MacroquadRenderer, Renderer},
+    replay::{playback_system, record_system, Replay, ReplayPlayer, ReplayRecorder},
+    resources::{self, FixedTime},
+    save::SaveSlot,
+    schedule::Stage,
+    systems::systems::{foreground_render_system, keyboard_system, save_load_system, time_system},
     world::World,
 };
 
-pub mod components;
-pub mod entity;
-pub mod query;
-pub mod resources;
-pub mod systems;
-pub mod world;
-
 fn window_conf() -> Conf {
     Conf {
         window_title: "Rustling".to_owned(),
@@ -39,23 +44,99 @@ async fn main() {
 }
 
 async fn run() -> Result<(), Box<dyn Error>> {
+    let options = Options::from_args()?;
+    let replay = options.replay.as_deref().map(Replay::load).transpose()?;
+    let seed = match &replay {
+        Some(replay) => replay.seed,
+        None => options.seed()?,
+    };
+    println!("seed: {}", seed);
+
     let mut world = World::new();
-    let mut resources = Resources::load_all(&mut world).await?;
+    game::setup_world(&mut world);
+    resources::load_all(&mut world, seed).await?;
+    let max_steps = replay
+        .as_ref()
+        .and_then(|replay| replay.max_steps)
+        .or(options.max_steps);
+    if let Some(max_steps) = max_steps {
+        world.resource_mut::<FixedTime>().max_steps = max_steps;
+    }
+
+    let mut inspector = Inspector::default();
+    inspector.register::<Position>("Position");
+    inspector.register::<LocalPosition>("LocalPosition");
+    inspector.register::<Velocity>("Velocity");
+    inspector.register::<Collider>("Collider");
+    inspector.register::<Health>("Health");
+    inspector.register::<Enemy>("Enemy");
+    inspector.register::<Player>("Player");
+    world.insert_resource(inspector);
 
+    let mut schedule = game::schedule();
+    if let Some(replay) = replay {
+        // F5/F9 in the replay use its own save, not the one on disk
+        world.insert_resource(SaveSlot::Memory(replay.save.clone()));
+        world.insert_resource(ReplayPlayer::new(replay));
+        schedule
+            .add_system(Stage::PreUpdate, playback_system)
+            .before(save_load_system);
+    } else {
+        schedule
+            .add_system(Stage::PreUpdate, time_system)
+            .before(save_load_system);
+        schedule
+            .add_system(Stage::PreUpdate, keyboard_system)
+            .before(save_load_system);
+    }
+    if options.record.is_some() {
+        let save = world.resource::<SaveSlot>().contents().ok();
+        let max_steps = world.resource::<FixedTime>().max_steps;
+        world.insert_resource(ReplayRecorder::new(seed, max_steps, save));
+        schedule
+            .add_system(Stage::PreUpdate, record_system)
+            .after(time_system)
+            .after(keyboard_system)
+            .after(playback_system)
+            .before(save_load_system);
+        // the replay is written when the window closes
+        prevent_quit();
+    }
+    schedule
+        .add_system(Stage::Render, inspector_system)
+        .after(foreground_render_system);
+
+    let mut renderer = MacroquadRenderer;
     loop {
-        clear_background(BLANK);
+        schedule.run(&mut world);
+        let draws = world.resource_mut::<DrawQueue>().take();
+        renderer.render(&world, draws);
 
-        tilemap_render_system(&resources.tiled_map, &mut world);
+        if let Some(player) = world.get_resource::<ReplayPlayer>()
+            && player.finished()
+        {
+            let checksum = world.checksum()?;
+            match player.replay.checksum {
+                Some(expected) if expected != checksum => {
+                    println!("replay diverged: checksum {} != {}", checksum, expected)
+                }
+                _ => println!("replay finished, checksum {}", checksum),
+            }
+            break;
+        }
 
-        animation_systems(&mut world);
-        input_systems(&mut world);
-        enemy_aggro_system(&mut world);
-        player_attack_system(&mut world);
+        if let Some(path) = &options.record
+            && is_quit_requested()
+        {
+            world.resource_scope::<ReplayRecorder, _>(|world, recorder| {
+                recorder.finish(world, path)
+            })?;
+            println!("replay written to {}", path);
+            break;
+        }
 
-        enemy_movement_systems(&mut world);
-        hit_systems(&mut world);
-        movement_systems(&mut world, &resources.tiled_map);
-        camera_systems(&mut world, &mut resources);
         next_frame().await;
     }
+
+    Ok(())
 }
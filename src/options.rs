@@ -0,0 +1,64 @@
+use std::error::Error;
+
+/// Command line flags of the game.
+#[derive(Debug, Default, Clone)]
+pub struct Options {
+    pub seed: Option<u64>,
+    /// Most FixedUpdate steps run in one frame to catch up, see `FixedTime`.
+    pub max_steps: Option<u32>,
+    /// Write a replay of the session here when the window closes.
+    pub record: Option<String>,
+    /// Play this replay instead of reading the keyboard.
+    pub replay: Option<String>,
+}
+
+impl Options {
+    pub fn from_args() -> Result<Self, Box<dyn Error>> {
+        Self::parse(std::env::args().skip(1))
+    }
+
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Self, Box<dyn Error>> {
+        let mut options = Options::default();
+        let mut args = args.into_iter();
+
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().ok_or_else(|| format!("{} needs a value", arg));
+            match arg.as_str() {
+                "--seed" => {
+                    let seed = value()?;
+                    options.seed = Some(
+                        seed.parse()
+                            .map_err(|e| format!("bad seed '{}': {}", seed, e))?,
+                    );
+                }
+                "--max-steps" => {
+                    let steps = value()?;
+                    match steps.parse() {
+                        Ok(0) => return Err("--max-steps needs at least 1".into()),
+                        Ok(steps) => options.max_steps = Some(steps),
+                        Err(e) => return Err(format!("bad max steps '{}': {}", steps, e).into()),
+                    }
+                }
+                "--record" => options.record = Some(value()?),
+                "--replay" => options.replay = Some(value()?),
+                _ => return Err(format!("unknown argument '{}'", arg).into()),
+            }
+        }
+
+        Ok(options)
+    }
+
+    /// `--seed`, then `RUSTLING_SEED`, random otherwise.
+    pub fn seed(&self) -> Result<u64, Box<dyn Error>> {
+        if let Some(seed) = self.seed {
+            return Ok(seed);
+        }
+
+        match std::env::var("RUSTLING_SEED") {
+            Ok(value) => Ok(value
+                .parse()
+                .map_err(|e| format!("bad RUSTLING_SEED '{}': {}", value, e))?),
+            Err(_) => Ok(rand::random()),
+        }
+    }
+}
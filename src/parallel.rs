@@ -0,0 +1,297 @@
+use std::any::{type_name, TypeId};
+
+use macroquad::math::Rect;
+
+use crate::{
+    commands::Commands,
+    entity::entity::Entity,
+    events::{EventReader, EventWriter, Events},
+    filter::QueryFilter,
+    pool::{Task, ThreadPool},
+    query::{self, AccessList, ComponentAccess, Fetch, QueryIter},
+    spatial::SpatialIndex,
+    world::World,
+};
+
+/// Components and resources a system says it touches. Two systems whose
+/// access doesn't conflict can run at the same time.
+///
+/// Everything declared has to be `Send + Sync`, since parallel systems
+/// reach it from worker threads.
+#[derive(Debug, Clone, Default)]
+pub struct SystemAccess {
+    components: Vec<ComponentAccess>,
+    resources: Vec<ComponentAccess>,
+}
+
+impl SystemAccess {
+    /// Adds everything a query type touches, e.g. `(&Position, &mut Enemy)`.
+    /// `&T` is only `Send + Sync` if `T: Sync`, and `&mut T` also needs
+    /// `T: Send`.
+    pub fn add_query<Q: Fetch<'static> + Send + Sync>(&mut self) {
+        let mut access = AccessList::new();
+        Q::access(&mut access);
+        self.components.extend_from_slice(access.as_slice());
+    }
+
+    pub fn add_resource<R: Send + Sync + 'static>(&mut self, mutable: bool) {
+        self.resources.push(ComponentAccess::of::<R>(mutable));
+    }
+
+    /// True if one of the two writes something the other one touches.
+    pub fn conflicts_with(&self, other: &SystemAccess) -> bool {
+        fn overlap(a: &[ComponentAccess], b: &[ComponentAccess]) -> bool {
+            a.iter().any(|a| {
+                b.iter()
+                    .any(|b| a.type_id == b.type_id && (a.mutable || b.mutable))
+            })
+        }
+
+        overlap(&self.components, &other.components) || overlap(&self.resources, &other.resources)
+    }
+
+    fn allows(declared: &[ComponentAccess], type_id: TypeId, mutable: bool) -> bool {
+        declared
+            .iter()
+            .any(|access| access.type_id == type_id && (access.mutable || !mutable))
+    }
+}
+
+/// What a parallel system gets instead of `&mut World`. It only hands out
+/// the components and resources the system declared, panicking on anything
+/// else, and structural changes have to go through `Commands` (`defer`).
+pub struct WorldCell<'w> {
+    world: &'w World,
+    name: &'static str,
+    access: &'w SystemAccess,
+    last_run: u32,
+    commands: Commands,
+    scoped: Vec<TypeId>,
+}
+
+// SAFETY: a cell only reaches the world through what its system declared,
+// which `SystemAccess` requires to be `Send + Sync`, plus the entity
+// allocator and the column and resource maps, which nothing writes while
+// cells are around. The commands it collects are `Send`.
+unsafe impl Send for WorldCell<'_> {}
+
+impl<'w> WorldCell<'w> {
+    fn new(world: &'w World, name: &'static str, access: &'w SystemAccess, last_run: u32) -> Self {
+        Self {
+            world,
+            name,
+            access,
+            last_run,
+            commands: Commands::new(),
+            scoped: Vec::new(),
+        }
+    }
+
+    /// Panics if the system touches a component it didn't declare, or
+    /// writes one it only declared for reading.
+    fn check_components(&self, access: &[ComponentAccess]) {
+        for access in access {
+            if !SystemAccess::allows(&self.access.components, access.type_id, access.mutable) {
+                panic!(
+                    "system {} uses {} {} without declaring it",
+                    self.name,
+                    if access.mutable { "&mut" } else { "&" },
+                    access.name
+                );
+            }
+        }
+    }
+
+    /// Same as `check_components` for resources. Resources taken out with
+    /// `resource_scope` count as missing, like they do on the world.
+    fn check_resource<R: 'static>(&self, mutable: bool) -> bool {
+        if !SystemAccess::allows(&self.access.resources, TypeId::of::<R>(), mutable) {
+            panic!(
+                "system {} uses resource {}{} without declaring it",
+                self.name,
+                if mutable { "mut " } else { "" },
+                type_name::<R>()
+            );
+        }
+        !self.scoped.contains(&TypeId::of::<R>())
+    }
+
+    fn check_query<'a, Q: Fetch<'a>, F: QueryFilter<'a>>(&self) {
+        let mut access = AccessList::new();
+        Q::access(&mut access);
+        F::access(&mut access);
+        self.check_components(access.as_slice());
+    }
+
+    /// See `World::query`.
+    pub fn query<'a, Q: Fetch<'a>>(&'a mut self) -> QueryIter<'a, Q, ()> {
+        self.query_filtered::<Q, ()>()
+    }
+
+    /// See `World::query_filtered`.
+    pub fn query_filtered<'a, Q: Fetch<'a>, F: QueryFilter<'a>>(
+        &'a mut self,
+    ) -> QueryIter<'a, Q, F> {
+        self.check_query::<Q, F>();
+        // SAFETY: the system declared everything the query touches and the
+        // schedule doesn't run it alongside anything conflicting with that.
+        // The cell stays borrowed for as long as the rows live.
+        unsafe { QueryIter::new(self.world, self.last_run) }
+    }
+
+    /// See `World::query_one`.
+    pub fn query_one<'a, Q: Fetch<'a>>(&'a mut self, entity: Entity) -> Option<Q::Item> {
+        self.check_query::<Q, ()>();
+        // SAFETY: same as `query_filtered`
+        unsafe { query::fetch_one::<Q>(self.world, entity) }
+    }
+
+    pub fn get_component<T: 'static>(&self, entity: Entity) -> Option<&T> {
+        self.check_components(&[ComponentAccess::of::<T>(false)]);
+        self.world.get_component::<T>(entity)
+    }
+
+    pub fn get_component_mut<T: 'static>(&mut self, entity: Entity) -> Option<&mut T> {
+        self.query_one::<&mut T>(entity)
+    }
+
+    pub fn is_alive(&self, entity: Entity) -> bool {
+        self.world.is_alive(entity)
+    }
+
+    pub fn get_resource<R: 'static>(&self) -> Option<&R> {
+        if !self.check_resource::<R>(false) {
+            return None;
+        }
+        self.world.get_resource::<R>()
+    }
+
+    pub fn get_resource_mut<R: 'static>(&mut self) -> Option<&mut R> {
+        if !self.check_resource::<R>(true) {
+            return None;
+        }
+        // SAFETY: the system declared the resource mutable, so nothing
+        // running alongside it touches it, and the cell stays borrowed
+        self.world
+            .resource_ptr::<R>()
+            .map(|resource| unsafe { &mut *resource })
+    }
+
+    /// Panics if the resource was never inserted.
+    pub fn resource<R: 'static>(&self) -> &R {
+        self.get_resource::<R>()
+            .unwrap_or_else(|| panic!("resource {} not found", type_name::<R>()))
+    }
+
+    pub fn resource_mut<R: 'static>(&mut self) -> &mut R {
+        self.get_resource_mut::<R>()
+            .unwrap_or_else(|| panic!("resource {} not found", type_name::<R>()))
+    }
+
+    /// See `World::resource_scope`. The resource can't be moved out of a
+    /// shared world, so instead it reads as missing through the cell until
+    /// `f` returns.
+    pub fn resource_scope<R: 'static, T>(
+        &mut self,
+        f: impl FnOnce(&mut WorldCell<'w>, &mut R) -> T,
+    ) -> T {
+        let resource: *mut R = self.resource_mut::<R>();
+        self.scoped.push(TypeId::of::<R>());
+        // SAFETY: the cell won't hand the resource out again while it's
+        // scoped, and nothing running alongside the system touches it
+        let result = f(self, unsafe { &mut *resource });
+        self.scoped.pop();
+        result
+    }
+
+    /// Needs `resource_mut::<Events<T>>()` declared.
+    pub fn event_writer<T: 'static>(&mut self) -> EventWriter<'_, T> {
+        EventWriter::new(self.resource_mut::<Events<T>>())
+    }
+
+    /// Needs `resource::<Events<T>>()` declared. Panics in FixedUpdate, see
+    /// `Events`.
+    pub fn event_reader<T: 'static>(&self) -> EventReader<'_, T> {
+        self.world.check_event_read::<T>();
+        EventReader::new(self.resource::<Events<T>>())
+    }
+
+    /// See `World::hurtboxes_overlapping`, needs `resource::<SpatialIndex>()`
+    /// declared.
+    pub fn hurtboxes_overlapping(&self, rect: Rect) -> Vec<Entity> {
+        self.get_resource::<SpatialIndex>()
+            .map_or_else(Vec::new, |index| index.hurtboxes_overlapping(rect))
+    }
+
+    /// Queues commands, applied with the rest of the stage's once the batch
+    /// is done.
+    pub fn defer(&mut self, mut commands: Commands) {
+        self.commands.append(&mut commands);
+    }
+}
+
+/// A system ready to run as part of a batch.
+pub(crate) struct BatchSystem<'a> {
+    pub name: &'static str,
+    pub access: &'a SystemAccess,
+    /// Change tick the system last ran at, see `Changed`.
+    pub last_run: u32,
+    pub system: &'a mut (dyn FnMut(&mut WorldCell) + Send),
+}
+
+/// Runs systems whose access doesn't conflict on the pool, then defers their
+/// commands in batch order so the result doesn't depend on which thread
+/// finished first.
+pub(crate) fn run_batch(world: &mut World, pool: &mut ThreadPool, batch: Vec<BatchSystem<'_>>) {
+    let mut results: Vec<Commands> = Vec::new();
+    results.resize_with(batch.len(), Commands::new);
+
+    let shared: &World = world;
+    let tasks: Vec<Task<'_>> = batch
+        .into_iter()
+        .zip(results.iter_mut())
+        .map(|(system, slot)| {
+            let mut cell = WorldCell::new(shared, system.name, system.access, system.last_run);
+            Box::new(move || {
+                (system.system)(&mut cell);
+                *slot = cell.commands;
+            }) as Task<'_>
+        })
+        .collect();
+    pool.run(tasks);
+
+    for commands in results {
+        world.defer(commands);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::WorldCell;
+    use crate::{
+        schedule::{Schedule, Stage},
+        world::World,
+    };
+
+    struct Position(f32);
+    struct Velocity(f32);
+
+    fn mover(world: &mut WorldCell) {
+        for (position, velocity) in world.query::<(&mut Position, &Velocity)>() {
+            position.0 += velocity.0;
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "uses &mut")]
+    fn undeclared_access_panics() {
+        let mut world = World::new();
+        world.spawn_entity().with(Position(0.0)).with(Velocity(1.0));
+
+        let mut schedule = Schedule::new();
+        schedule
+            .add_parallel_system(Stage::Update, mover)
+            .access::<(&Position, &Velocity)>();
+        schedule.run(&mut world);
+    }
+}
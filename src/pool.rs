@@ -0,0 +1,139 @@
+use std::{
+    any::Any,
+    mem,
+    panic::{self, AssertUnwindSafe},
+    sync::{mpsc, Arc, Condvar, Mutex},
+    thread::{self, JoinHandle},
+};
+
+type Job = Box<dyn FnOnce() + Send + 'static>;
+
+/// A task borrowing from the caller of `ThreadPool::run`.
+pub(crate) type Task<'a> = Box<dyn FnOnce() + Send + 'a>;
+
+/// Worker threads kept around between frames, so running systems in parallel
+/// doesn't spawn threads every fixed step. Starts empty and grows to the
+/// biggest batch it was asked to run.
+#[derive(Default)]
+pub(crate) struct ThreadPool {
+    sender: Option<mpsc::Sender<Job>>,
+    receiver: Option<Arc<Mutex<mpsc::Receiver<Job>>>>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+/// Counts the tasks still running and keeps the first panic.
+#[derive(Default)]
+struct Latch {
+    state: Mutex<(usize, Option<Box<dyn Any + Send>>)>,
+    done: Condvar,
+}
+
+impl Latch {
+    fn finish(&self, result: thread::Result<()>) {
+        let mut state = self.state.lock().unwrap();
+        state.0 -= 1;
+        if let Err(panic) = result {
+            state.1.get_or_insert(panic);
+        }
+        self.done.notify_all();
+    }
+
+    fn start(&self) {
+        self.state.lock().unwrap().0 += 1;
+    }
+
+    fn wait(&self) {
+        let mut state = self.state.lock().unwrap();
+        while state.0 > 0 {
+            state = self.done.wait(state).unwrap();
+        }
+    }
+}
+
+/// Waits for the tasks handed to the workers even if the caller unwinds,
+/// they borrow from its stack.
+struct WaitOnDrop<'a>(&'a Latch);
+
+impl Drop for WaitOnDrop<'_> {
+    fn drop(&mut self) {
+        self.0.wait();
+    }
+}
+
+impl ThreadPool {
+    /// Runs every task and returns once all of them finished, the first one
+    /// on this thread and the others on workers. If a task panicked the panic
+    /// is passed on after the others finished.
+    pub fn run(&mut self, mut tasks: Vec<Task<'_>>) {
+        if tasks.is_empty() {
+            return;
+        }
+        let first = tasks.remove(0);
+        self.grow(tasks.len());
+
+        let latch = Arc::new(Latch::default());
+        let waiting = WaitOnDrop(&latch);
+
+        let sender = self.sender.as_ref().unwrap();
+        for task in tasks {
+            let running = latch.clone();
+            let job: Task<'_> = Box::new(move || {
+                let result = panic::catch_unwind(AssertUnwindSafe(task));
+                running.finish(result);
+            });
+            // SAFETY: `waiting` blocks until every job ran before this
+            // returns or unwinds, so what the tasks borrow outlives them
+            let job = unsafe { mem::transmute::<Task<'_>, Job>(job) };
+            latch.start();
+            if sender.send(job).is_err() {
+                latch.finish(Ok(()));
+                panic!("worker threads stopped");
+            }
+        }
+
+        let first = panic::catch_unwind(AssertUnwindSafe(first));
+        drop(waiting);
+
+        if let Some(panic) = latch.state.lock().unwrap().1.take() {
+            panic::resume_unwind(panic);
+        }
+        if let Err(panic) = first {
+            panic::resume_unwind(panic);
+        }
+    }
+
+    fn grow(&mut self, workers: usize) {
+        let receiver = self
+            .receiver
+            .get_or_insert_with(|| {
+                let (sender, receiver) = mpsc::channel();
+                self.sender = Some(sender);
+                Arc::new(Mutex::new(receiver))
+            })
+            .clone();
+
+        while self.workers.len() < workers {
+            let receiver = receiver.clone();
+            self.workers.push(thread::spawn(move || {
+                loop {
+                    // the lock is released before the job runs
+                    let job = receiver.lock().unwrap().recv();
+                    match job {
+                        Ok(job) => job(),
+                        Err(_) => break,
+                    }
+                }
+            }));
+        }
+    }
+}
+
+impl Drop for ThreadPool {
+    fn drop(&mut self) {
+        // closing the channel stops the workers once they're idle
+        self.sender = None;
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
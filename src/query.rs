@@ -1,299 +1,484 @@
+use std::{
+    any::{type_name, TypeId},
+    marker::PhantomData,
+};
+
 use crate::{
-    components::{
-        collider::Collider,
-        enemy::{self, Enemy},
-        player::Player,
-        position::Position,
-        sprite::Sprite,
-        velocity::Velocity,
-    },
+    entity::entity::{Entities, Entity},
+    filter::QueryFilter,
+    storage::ColumnPtr,
     world::World,
 };
 
-pub trait ComponentQuery<'a> {
-    type Output; // implementor needs to specify their return values
-    fn find_entities(world: &'a mut World) -> Vec<Self::Output>;
+/// Which component type a query element touches and how.
+#[derive(Debug, Clone, Copy)]
+pub struct ComponentAccess {
+    pub type_id: TypeId,
+    pub name: &'static str,
+    pub mutable: bool,
 }
 
-impl<'a> ComponentQuery<'a> for (&'a Sprite, &'a Position) {
-    type Output = (&'a Sprite, &'a Position);
-
-    fn find_entities(world: &'a mut World) -> Vec<Self::Output> {
-        let mut entities = Vec::new();
-        for entity in 0..world.entities_count {
-            if let (Some(sprite), Some(position)) = (
-                world.get_component::<Sprite>(entity),
-                world.get_component::<Position>(entity),
-            ) {
-                entities.push((sprite, position));
-            }
+impl ComponentAccess {
+    pub fn of<T: 'static>(mutable: bool) -> Self {
+        Self {
+            type_id: TypeId::of::<T>(),
+            name: type_name::<T>(),
+            mutable,
         }
-        entities
     }
 }
 
-impl<'a> ComponentQuery<'a> for (&'a Position, &'a Collider, &'a Enemy) {
-    type Output = (&'a Position, &'a Collider, &'a Enemy);
-
-    fn find_entities(world: &'a mut World) -> Vec<Self::Output> {
-        let mut entities = Vec::new();
-        for entity in 0..world.entities_count {
-            if let (Some(position), Some(collider), Some(enemy)) = (
-                world.get_component::<Position>(entity),
-                world.get_component::<Collider>(entity),
-                world.get_component::<Enemy>(entity),
-            ) {
-                entities.push((position, collider, enemy));
-            }
+/// Enough for a query of eight elements and a few filters.
+const MAX_ACCESS: usize = 16;
+
+/// What a query touches, kept on the stack so running a query doesn't
+/// allocate.
+#[derive(Clone, Copy)]
+pub struct AccessList {
+    items: [ComponentAccess; MAX_ACCESS],
+    len: usize,
+}
+
+impl AccessList {
+    pub fn new() -> Self {
+        Self {
+            items: [ComponentAccess::of::<()>(false); MAX_ACCESS],
+            len: 0,
         }
-        entities
     }
-}
 
-impl<'a> ComponentQuery<'a> for (&'a mut Velocity, &'a mut Player) {
-    type Output = (&'a mut Velocity, &'a mut Player);
+    pub fn push(&mut self, access: ComponentAccess) {
+        assert!(
+            self.len < MAX_ACCESS,
+            "queries can't touch more than {} components",
+            MAX_ACCESS
+        );
+        self.items[self.len] = access;
+        self.len += 1;
+    }
 
-    fn find_entities(world: &'a mut World) -> Vec<Self::Output> {
-        let mut entities = Vec::new();
-        unsafe {
-            let world_ptr = &raw mut *world;
+    pub fn as_slice(&self) -> &[ComponentAccess] {
+        &self.items[..self.len]
+    }
+}
 
-            for entity in 0..(*world_ptr).entities_count {
-                if let (Some(velocity), Some(player)) = (
-                    (*world_ptr).get_component_mut::<Velocity>(entity),
-                    (*world_ptr).get_component_mut::<Player>(entity),
-                ) {
-                    entities.push((velocity, player));
-                }
-            }
-        }
-        entities
+impl Default for AccessList {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
-impl<'a> ComponentQuery<'a> for (&'a Position, &'a Collider, &'a mut Enemy) {
-    type Output = (&'a Position, &'a Collider, &'a mut Enemy);
-
-    fn find_entities(world: &'a mut World) -> Vec<Self::Output> {
-        let mut entities = Vec::new();
-
-        unsafe {
-            let world_ptr = &raw mut *world;
-            for entity in 0..(*world_ptr).entities_count {
-                if let (Some(position), Some(collider), Some(enemy)) = (
-                    (*world_ptr).get_component::<Position>(entity),
-                    (*world_ptr).get_component::<Collider>(entity),
-                    (*world_ptr).get_component_mut::<Enemy>(entity),
-                ) {
-                    entities.push((position, collider, enemy));
-                }
-            }
-        }
+/// A single element of a query, e.g. `&Position` or `&mut Enemy`.
+/// Tuples of elements are elements too, so queries can nest.
+///
+/// `prepare` looks up the columns once per query and `fetch` then only
+/// indexes into them per entity.
+pub trait Fetch<'a> {
+    type Item;
+    type State: Copy;
+
+    fn access(access: &mut AccessList);
+
+    /// Returns `None` if a required column doesn't exist, so nothing can match.
+    ///
+    /// # Safety
+    /// Nothing else may touch what this element borrows mutably, or write
+    /// what it reads, while its rows are around.
+    unsafe fn prepare(world: &'a World) -> Option<Self::State>;
+
+    /// The smallest known list of entity indices that can match, so sparse
+    /// components drive the iteration instead of scanning every slot.
+    ///
+    /// # Safety
+    /// `state` must come from `prepare` and the world must be unchanged since.
+    unsafe fn candidates(state: Self::State) -> Option<&'a [usize]>;
+
+    /// Whether `fetch` would return a row, without borrowing the components
+    /// or marking them changed.
+    ///
+    /// # Safety
+    /// Same as `candidates`.
+    unsafe fn matches(state: Self::State, index: usize) -> bool;
+
+    /// # Safety
+    /// The caller must make sure nothing else holds a reference to the
+    /// components this element borrows mutably (see `check_access`), and must
+    /// not fetch the same index twice.
+    unsafe fn fetch(state: Self::State, index: usize) -> Option<Self::Item>;
+}
+
+impl<'a, T: 'static> Fetch<'a> for &'a T {
+    type Item = &'a T;
+    type State = ColumnPtr<T>;
 
-        entities
+    fn access(access: &mut AccessList) {
+        access.push(ComponentAccess::of::<T>(false));
     }
-}
 
-impl<'a> ComponentQuery<'a> for (&'a mut Sprite, &'a Velocity, &'a Player) {
-    type Output = (&'a mut Sprite, &'a Velocity, &'a Player);
-
-    fn find_entities(world: &'a mut World) -> Vec<Self::Output> {
-        let mut entities = Vec::new();
-
-        unsafe {
-            let world_ptr = &raw mut *world;
-            for entity in 0..(*world_ptr).entities_count {
-                if let (Some(sprite), Some(velocity), Some(player)) = (
-                    (*world_ptr).get_component_mut::<Sprite>(entity),
-                    (*world_ptr).get_component::<Velocity>(entity),
-                    (*world_ptr).get_component::<Player>(entity),
-                ) {
-                    entities.push((sprite, velocity, player));
-                }
-            }
-        }
+    unsafe fn prepare(world: &'a World) -> Option<Self::State> {
+        world.column_ptr::<T>()
+    }
+
+    unsafe fn candidates(state: Self::State) -> Option<&'a [usize]> {
+        unsafe { state.entities() }
+    }
+
+    unsafe fn matches(state: Self::State, index: usize) -> bool {
+        unsafe { state.contains(index) }
+    }
 
-        entities
+    unsafe fn fetch(state: Self::State, index: usize) -> Option<Self::Item> {
+        unsafe { state.get(index) }
     }
 }
 
-impl<'a> ComponentQuery<'a> for (&'a mut Sprite, &'a Velocity, &'a mut Enemy) {
-    type Output = (&'a mut Sprite, &'a Velocity, &'a mut Enemy);
-
-    fn find_entities(world: &'a mut World) -> Vec<Self::Output> {
-        let mut entities = Vec::new();
-
-        unsafe {
-            let world_ptr = &raw mut *world;
-            for entity in 0..(*world_ptr).entities_count {
-                if let (Some(sprite), Some(velocity), Some(enemy)) = (
-                    (*world_ptr).get_component_mut::<Sprite>(entity),
-                    (*world_ptr).get_component::<Velocity>(entity),
-                    (*world_ptr).get_component_mut::<Enemy>(entity),
-                ) {
-                    entities.push((sprite, velocity, enemy));
-                }
-            }
-        }
+impl<'a, T: 'static> Fetch<'a> for &'a mut T {
+    type Item = &'a mut T;
+    type State = ColumnPtr<T>;
 
-        entities
+    fn access(access: &mut AccessList) {
+        access.push(ComponentAccess::of::<T>(true));
+    }
+
+    unsafe fn prepare(world: &'a World) -> Option<Self::State> {
+        world.column_ptr::<T>()
+    }
+
+    unsafe fn candidates(state: Self::State) -> Option<&'a [usize]> {
+        unsafe { state.entities() }
+    }
+
+    unsafe fn matches(state: Self::State, index: usize) -> bool {
+        unsafe { state.contains(index) }
+    }
+
+    unsafe fn fetch(state: Self::State, index: usize) -> Option<Self::Item> {
+        unsafe { state.get_mut(index) }
     }
 }
 
-impl<'a> ComponentQuery<'a> for (&'a mut Position, &'a Velocity, &'a Collider) {
-    type Output = (&'a mut Position, &'a Velocity, &'a Collider);
-
-    fn find_entities(world: &'a mut World) -> Vec<Self::Output> {
-        let mut entities = Vec::new();
-        unsafe {
-            let world_ptr = &raw mut *world;
-
-            for entity in 0..(*world_ptr).entities_count {
-                if let (Some(position), Some(velocity), Some(collider)) = (
-                    (*world_ptr).get_component_mut::<Position>(entity),
-                    (*world_ptr).get_component::<Velocity>(entity),
-                    (*world_ptr).get_component::<Collider>(entity),
-                ) {
-                    entities.push((position, velocity, collider));
-                }
-            }
-        }
-        entities
+/// Yields the entity the row belongs to, e.g. `query::<(Entity, &Enemy)>()`.
+impl<'a> Fetch<'a> for Entity {
+    type Item = Entity;
+    type State = *const Entities;
+
+    fn access(_access: &mut AccessList) {}
+
+    unsafe fn prepare(world: &'a World) -> Option<Self::State> {
+        Some(world.entity_allocator() as *const Entities)
+    }
+
+    unsafe fn candidates(_state: Self::State) -> Option<&'a [usize]> {
+        None
+    }
+
+    unsafe fn matches(state: Self::State, index: usize) -> bool {
+        unsafe { (*state).get(index).is_some() }
+    }
+
+    unsafe fn fetch(state: Self::State, index: usize) -> Option<Self::Item> {
+        unsafe { (*state).get(index) }
     }
 }
 
-impl<'a> ComponentQuery<'a> for (&'a Position, &'a Player) {
-    type Output = (&'a Position, &'a Player);
-
-    fn find_entities(world: &'a mut World) -> Vec<Self::Output> {
-        let mut entities = Vec::new();
-        for entity in 0..world.entities_count {
-            if let (Some(position), Some(player)) = (
-                world.get_component::<Position>(entity),
-                world.get_component::<Player>(entity),
-            ) {
-                entities.push((position, player));
-            }
-        }
-        entities
+/// Matches every entity, yielding `None` where the element doesn't, e.g.
+/// `query::<(&Position, Option<&PreviousPosition>)>()`. Still counts as
+/// access to the component for parallel systems.
+impl<'a, F: Fetch<'a>> Fetch<'a> for Option<F> {
+    type Item = Option<F::Item>;
+    type State = Option<F::State>;
+
+    fn access(access: &mut AccessList) {
+        F::access(access);
+    }
+
+    unsafe fn prepare(world: &'a World) -> Option<Self::State> {
+        unsafe { Some(F::prepare(world)) }
+    }
+
+    unsafe fn candidates(_state: Self::State) -> Option<&'a [usize]> {
+        None
+    }
+
+    unsafe fn matches(_state: Self::State, _index: usize) -> bool {
+        true
+    }
+
+    unsafe fn fetch(state: Self::State, index: usize) -> Option<Self::Item> {
+        unsafe { Some(state.and_then(|state| F::fetch(state, index))) }
     }
 }
 
-impl<'a> ComponentQuery<'a> for (&'a Position, &'a mut Player) {
-    type Output = (&'a Position, &'a mut Player);
-
-    fn find_entities(world: &'a mut World) -> Vec<Self::Output> {
-        let mut entities = Vec::new();
-        unsafe {
-            let world_ptr = &raw mut *world;
-            for entity in 0..world.entities_count {
-                if let (Some(position), Some(player)) = (
-                    (*world_ptr).get_component::<Position>(entity),
-                    (*world_ptr).get_component_mut::<Player>(entity),
-                ) {
-                    entities.push((position, player));
+macro_rules! impl_fetch_for_tuple {
+    ($($name:ident),+) => {
+        #[allow(non_snake_case)]
+        impl<'a, $($name: Fetch<'a>),+> Fetch<'a> for ($($name,)+) {
+            type Item = ($($name::Item,)+);
+            type State = ($($name::State,)+);
+
+            fn access(access: &mut AccessList) {
+                $($name::access(access);)+
+            }
+
+            unsafe fn prepare(world: &'a World) -> Option<Self::State> {
+                unsafe { Some(($($name::prepare(world)?,)+)) }
+            }
+
+            unsafe fn candidates(state: Self::State) -> Option<&'a [usize]> {
+                let ($($name,)+) = state;
+                let mut smallest: Option<&'a [usize]> = None;
+                $(
+                    if let Some(candidates) = unsafe { $name::candidates($name) } {
+                        if smallest.is_none_or(|smallest| candidates.len() < smallest.len()) {
+                            smallest = Some(candidates);
+                        }
+                    }
+                )+
+                smallest
+            }
+
+            unsafe fn matches(state: Self::State, index: usize) -> bool {
+                let ($($name,)+) = state;
+                unsafe { $($name::matches($name, index))&&+ }
+            }
+
+            unsafe fn fetch(state: Self::State, index: usize) -> Option<Self::Item> {
+                // check every element first, a `&mut` element marks its
+                // component changed as soon as it's fetched
+                if !unsafe { Self::matches(state, index) } {
+                    return None;
                 }
+                let ($($name,)+) = state;
+                unsafe { Some(($($name::fetch($name, index)?,)+)) }
             }
         }
-        entities
-    }
+    };
 }
 
-impl<'a> ComponentQuery<'a> for (&'a Position, &'a mut Enemy) {
-    type Output = (&'a Position, &'a mut Enemy);
-
-    fn find_entities(world: &'a mut World) -> Vec<Self::Output> {
-        let mut entities = Vec::new();
-        unsafe {
-            let world_ptr = &raw mut *world;
-            for entity in 0..world.entities_count {
-                if let (Some(position), Some(enemy)) = (
-                    (*world_ptr).get_component::<Position>(entity),
-                    (*world_ptr).get_component_mut::<Enemy>(entity),
-                ) {
-                    entities.push((position, enemy));
-                }
+impl_fetch_for_tuple!(A);
+impl_fetch_for_tuple!(A, B);
+impl_fetch_for_tuple!(A, B, C);
+impl_fetch_for_tuple!(A, B, C, D);
+impl_fetch_for_tuple!(A, B, C, D, E);
+impl_fetch_for_tuple!(A, B, C, D, E, F);
+impl_fetch_for_tuple!(A, B, C, D, E, F, G);
+impl_fetch_for_tuple!(A, B, C, D, E, F, G, H);
+
+/// Panics if the same component is borrowed mutably more than once, or both
+/// mutably and immutably, since that would hand out aliasing references.
+/// Queries touch a handful of components, so comparing every pair is cheap.
+pub fn check_access(access: &[ComponentAccess]) {
+    for (i, a) in access.iter().enumerate() {
+        for b in &access[i + 1..] {
+            if a.type_id == b.type_id && (a.mutable || b.mutable) {
+                panic!(
+                    "query borrows {} mutably while it is already borrowed",
+                    a.name
+                );
             }
         }
-        entities
     }
 }
 
-impl<'a> ComponentQuery<'a> for &'a mut Sprite {
-    type Output = &'a mut Sprite;
-
-    fn find_entities(world: &'a mut World) -> Vec<Self::Output> {
-        let mut entities = Vec::new();
+pub trait ComponentQuery<'a>: Fetch<'a> + Sized {
+    fn find_entities<F: QueryFilter<'a>>(world: &'a mut World) -> QueryIter<'a, Self, F>;
+    fn find_one(world: &'a mut World, entity: Entity) -> Option<Self::Item>;
+}
 
-        unsafe {
-            let world_ptr = &raw mut *world;
-            for entity in 0..(*world_ptr).entities_count {
-                if let Some(sprite) = (*world_ptr).get_component_mut::<Sprite>(entity) {
-                    entities.push(sprite);
-                }
-            }
-        }
+impl<'a, Q: Fetch<'a>> ComponentQuery<'a> for Q {
+    fn find_one(world: &'a mut World, entity: Entity) -> Option<Self::Item> {
+        // SAFETY: the world is borrowed mutably for as long as the row lives
+        unsafe { fetch_one::<Q>(world, entity) }
+    }
 
-        entities
+    fn find_entities<F: QueryFilter<'a>>(world: &'a mut World) -> QueryIter<'a, Self, F> {
+        // SAFETY: the world is borrowed mutably for as long as the iterator
+        // and its rows live
+        unsafe { QueryIter::new(world, world.last_change_tick()) }
     }
 }
 
-impl<'a> ComponentQuery<'a> for &'a mut Enemy {
-    type Output = &'a mut Enemy;
+/// `query_one` through a shared borrow of the world.
+///
+/// # Safety
+/// Nothing else may touch what `Q` borrows mutably, or write what it reads,
+/// while the row is around.
+pub(crate) unsafe fn fetch_one<'a, Q: Fetch<'a>>(
+    world: &'a World,
+    entity: Entity,
+) -> Option<Q::Item> {
+    let mut access = AccessList::new();
+    Q::access(&mut access);
+    check_access(access.as_slice());
+
+    if !world.is_alive(entity) {
+        return None;
+    }
 
-    fn find_entities(world: &'a mut World) -> Vec<Self::Output> {
-        let mut entities = Vec::new();
+    // SAFETY: check_access rules out aliasing and only one row is fetched
+    unsafe { Q::fetch(Q::prepare(world)?, entity.index) }
+}
 
-        unsafe {
-            let world_ptr = &raw mut *world;
-            for entity in 0..(*world_ptr).entities_count {
-                if let Some(enemy) = (*world_ptr).get_component_mut::<Enemy>(entity) {
-                    entities.push(enemy);
-                }
+/// Lazily walks the entities matching a query, fetching one row per `next`.
+/// Holds the world borrowed for as long as the rows it handed out live.
+pub struct QueryIter<'a, Q: Fetch<'a>, F: QueryFilter<'a>> {
+    state: Option<(Q::State, F::State)>,
+    candidates: Option<&'a [usize]>,
+    entities: &'a Entities,
+    next: usize,
+    end: usize,
+    _world: PhantomData<&'a mut World>,
+}
+
+impl<'a, Q: Fetch<'a>, F: QueryFilter<'a>> QueryIter<'a, Q, F> {
+    /// `last_run` is the change tick `Changed`/`Added` filters compare
+    /// against.
+    ///
+    /// # Safety
+    /// Nothing else may touch what `Q` borrows mutably, or write what `Q`
+    /// and `F` read, while the iterator and its rows are around.
+    pub(crate) unsafe fn new(world: &'a World, last_run: u32) -> Self {
+        let mut access = AccessList::new();
+        Q::access(&mut access);
+        check_access(access.as_slice());
+
+        // SAFETY: up to the caller, and the world stays borrowed for 'a, so
+        // the columns can't move or change size meanwhile
+        let state = unsafe { Q::prepare(world).zip(F::prepare(world, last_run)) };
+        let candidates = state.and_then(|(state, filter)| unsafe {
+            match (Q::candidates(state), F::candidates(filter)) {
+                (Some(a), Some(b)) => Some(if b.len() < a.len() { b } else { a }),
+                (a, b) => a.or(b),
             }
+        });
+
+        let entities = world.entity_allocator();
+
+        let end = match (state, candidates) {
+            (None, _) => 0,
+            (Some(_), Some(candidates)) => candidates.len(),
+            (Some(_), None) => world.entities_count(),
+        };
+
+        Self {
+            state,
+            candidates,
+            entities,
+            next: 0,
+            end,
+            _world: PhantomData,
         }
+    }
+
+    /// The only matching row. Panics if there are none or several, use
+    /// `get_single` if that can happen.
+    pub fn single(self) -> Q::Item {
+        let name = type_name::<Q>();
+        self.get_single()
+            .unwrap_or_else(|| panic!("query {} doesn't match exactly one entity", name))
+    }
 
-        entities
+    /// The only matching row, `None` if there are none or several.
+    pub fn get_single(mut self) -> Option<Q::Item> {
+        let item = self.next()?;
+        self.next().is_none().then_some(item)
     }
 }
 
-impl<'a> ComponentQuery<'a> for &'a mut Player {
-    type Output = &'a mut Player;
+impl<'a, Q: Fetch<'a>, F: QueryFilter<'a>> Iterator for QueryIter<'a, Q, F> {
+    type Item = Q::Item;
 
-    fn find_entities(world: &'a mut World) -> Vec<Self::Output> {
-        let mut entities = Vec::new();
+    fn next(&mut self) -> Option<Self::Item> {
+        let (state, filter) = self.state?;
 
-        unsafe {
-            let world_ptr = &raw mut *world;
-            for entity in 0..(*world_ptr).entities_count {
-                if let Some(player) = (*world_ptr).get_component_mut::<Player>(entity) {
-                    entities.push(player);
+        // SAFETY: check_access rules out two elements aliasing the same
+        // column, and every slot is visited once so rows don't overlap.
+        // Filters only look at a row before it is fetched.
+        let visit = |index: usize| unsafe {
+            if F::matches(filter, index) {
+                Q::fetch(state, index)
+            } else {
+                None
+            }
+        };
+
+        // separate loops so the hot path doesn't branch on the candidates
+        match self.candidates {
+            Some(candidates) => {
+                while let Some(&index) = candidates.get(self.next) {
+                    self.next += 1;
+                    if let Some(item) = visit(index) {
+                        return Some(item);
+                    }
+                }
+            }
+            None => {
+                while self.next < self.end {
+                    let index = self.next;
+                    self.next += 1;
+                    // free slots have no components, but `Option` elements
+                    // match them anyway
+                    if self.entities.get(index).is_none() {
+                        continue;
+                    }
+                    if let Some(item) = visit(index) {
+                        return Some(item);
+                    }
                 }
             }
         }
 
-        entities
+        None
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, Some(self.end - self.next))
     }
 }
 
-impl<'a> ComponentQuery<'a> for (&'a Position, &'a mut Velocity, &'a mut Enemy) {
-    type Output = (&'a Position, &'a mut Velocity, &'a mut Enemy);
-
-    fn find_entities(world: &'a mut World) -> Vec<Self::Output> {
-        let mut entities = Vec::new();
-        unsafe {
-            let world_ptr = &raw mut *world;
-
-            for entity in 0..(*world_ptr).entities_count {
-                if let (Some(position), Some(velocity), Some(enemy)) = (
-                    (*world_ptr).get_component::<Position>(entity),
-                    (*world_ptr).get_component_mut::<Velocity>(entity),
-                    (*world_ptr).get_component_mut::<Enemy>(entity),
-                ) {
-                    entities.push((position, velocity, enemy));
-                }
-            }
+#[cfg(test)]
+mod tests {
+    use crate::{entity::entity::Entity, filter::Changed, world::World};
+
+    struct Position(f32);
+    struct Velocity(f32);
+
+    #[test]
+    fn partial_rows_are_not_marked_changed() {
+        let mut world = World::new();
+        let moving = world
+            .spawn_entity()
+            .with(Position(0.0))
+            .with(Velocity(1.0))
+            .id();
+        world.spawn_entity().with(Position(0.0));
+        world.clear_trackers();
+
+        for (position, velocity) in world.query::<(&mut Position, &Velocity)>() {
+            position.0 += velocity.0;
         }
-        entities
+
+        let changed: Vec<_> = world
+            .query_filtered::<Entity, Changed<Position>>()
+            .collect();
+        assert_eq!(changed, vec![moving]);
+    }
+
+    #[test]
+    #[should_panic(expected = "query borrows")]
+    fn aliasing_queries_panic() {
+        let mut world = World::new();
+        world.spawn_entity().with(Position(0.0));
+
+        world.query::<(&mut Position, &Position)>().count();
+    }
+
+    #[test]
+    fn optional_queries_skip_despawned_slots() {
+        let mut world = World::new();
+        let entity = world.spawn_entity().with(Position(0.0)).id();
+        world.despawn(entity);
+
+        assert_eq!(world.query::<Option<&Position>>().count(), 0);
     }
 }
@@ -0,0 +1,99 @@
+use macroquad::{
+    camera::{set_camera, Camera2D},
+    color::{Color, WHITE},
+    math::{Rect, Vec2},
+    shapes::draw_rectangle_lines,
+    texture::{draw_texture_ex, DrawTextureParams, Texture2D},
+    window::clear_background,
+};
+use macroquad_tiled::Map;
+
+use crate::world::{World, WORLD_HEIGHT, WORLD_WIDTH};
+
+/// Something to draw this frame, in world coordinates. Render systems only
+/// push these, a `Renderer` turns them into pixels (or doesn't, headless).
+#[derive(Debug, Clone)]
+pub enum DrawCommand {
+    Clear(Color),
+    TileLayer(&'static str),
+    Sprite {
+        texture: Texture2D,
+        position: Vec2,
+        source: Option<Rect>,
+        dest_size: Option<Vec2>,
+        flip_x: bool,
+    },
+    RectLines {
+        rect: Rect,
+        thickness: f32,
+        color: Color,
+    },
+}
+
+/// Draw commands of the current frame, in order. Emptied by whoever presents
+/// the frame.
+#[derive(Debug, Default)]
+pub struct DrawQueue {
+    commands: Vec<DrawCommand>,
+}
+
+impl DrawQueue {
+    pub fn push(&mut self, command: DrawCommand) {
+        self.commands.push(command);
+    }
+
+    pub fn commands(&self) -> &[DrawCommand] {
+        &self.commands
+    }
+
+    pub fn take(&mut self) -> Vec<DrawCommand> {
+        std::mem::take(&mut self.commands)
+    }
+}
+
+pub trait Renderer {
+    fn render(&mut self, world: &World, commands: Vec<DrawCommand>);
+}
+
+/// Draws through macroquad with the world's `Camera2D`, needs a window.
+pub struct MacroquadRenderer;
+
+impl Renderer for MacroquadRenderer {
+    fn render(&mut self, world: &World, commands: Vec<DrawCommand>) {
+        set_camera(world.resource::<Camera2D>());
+
+        for command in commands {
+            match command {
+                DrawCommand::Clear(color) => clear_background(color),
+                DrawCommand::TileLayer(layer) => world.resource::<Map>().draw_tiles(
+                    layer,
+                    Rect::new(0.0, 0.0, WORLD_WIDTH, WORLD_HEIGHT),
+                    None,
+                ),
+                DrawCommand::Sprite {
+                    texture,
+                    position,
+                    source,
+                    dest_size,
+                    flip_x,
+                } => draw_texture_ex(
+                    &texture,
+                    position.x,
+                    position.y,
+                    WHITE,
+                    DrawTextureParams {
+                        source,
+                        dest_size,
+                        flip_x,
+                        ..Default::default()
+                    },
+                ),
+                DrawCommand::RectLines {
+                    rect,
+                    thickness,
+                    color,
+                } => draw_rectangle_lines(rect.x, rect.y, rect.w, rect.h, thickness, color),
+            }
+        }
+    }
+}
@@ -0,0 +1,151 @@
+// nanoserde's DeJson derive trips this on Option fields
+#![allow(clippy::question_mark)]
+
+use std::{error::Error, fs};
+
+use nanoserde::{DeJson, SerJson};
+
+use crate::{
+    headless::HeadlessGame,
+    resources::{InputState, Time},
+    world::World,
+};
+
+const REPLAY_VERSION: u32 = 1;
+
+/// A session that can be played again: the seed, the save F9 would have
+/// loaded, then the frame time and input of every frame. Mouse edits in the
+/// inspector aren't recorded. A session that used them won't replay the same.
+#[derive(Debug, Clone, SerJson, DeJson)]
+pub struct Replay {
+    pub version: u32,
+    pub seed: u64,
+    /// `FixedTime::max_steps` while recording. Older replays don't have it
+    /// and play with the default.
+    pub max_steps: Option<u32>,
+    /// Save slot contents when recording started. Playback saves and loads
+    /// in memory starting from this.
+    pub save: Option<String>,
+    pub frames: Vec<ReplayFrame>,
+    /// `World::checksum` after the last frame.
+    pub checksum: Option<u64>,
+}
+
+#[derive(Debug, Clone, Copy, SerJson, DeJson)]
+pub struct ReplayFrame {
+    pub delta: f32,
+    pub input: InputState,
+}
+
+impl Replay {
+    pub fn new(seed: u64, max_steps: u32, save: Option<String>) -> Self {
+        Self {
+            version: REPLAY_VERSION,
+            seed,
+            max_steps: Some(max_steps),
+            save,
+            frames: Vec::new(),
+            checksum: None,
+        }
+    }
+
+    pub fn load(path: &str) -> Result<Self, Box<dyn Error>> {
+        let replay = Replay::deserialize_json(&fs::read_to_string(path)?)
+            .map_err(|e| format!("{}: {}", path, e))?;
+        if replay.version != REPLAY_VERSION {
+            return Err(format!("unsupported replay version {}", replay.version).into());
+        }
+        Ok(replay)
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), Box<dyn Error>> {
+        fs::write(path, self.serialize_json())?;
+        Ok(())
+    }
+}
+
+/// Collects the session while `record_system` runs.
+pub struct ReplayRecorder {
+    pub replay: Replay,
+}
+
+impl ReplayRecorder {
+    pub fn new(seed: u64, max_steps: u32, save: Option<String>) -> Self {
+        Self {
+            replay: Replay::new(seed, max_steps, save),
+        }
+    }
+
+    /// Stamps the current world checksum and writes the replay out.
+    pub fn finish(&mut self, world: &World, path: &str) -> Result<(), Box<dyn Error>> {
+        self.replay.checksum = Some(world.checksum()?);
+        self.replay.save(path)
+    }
+}
+
+/// Stores this frame's time and input. Runs after `time_system` and
+/// `keyboard_system`, before anything reads them.
+pub fn record_system(world: &mut World) {
+    let frame = ReplayFrame {
+        delta: world.resource::<Time>().delta,
+        input: *world.resource::<InputState>(),
+    };
+    world
+        .resource_mut::<ReplayRecorder>()
+        .replay
+        .frames
+        .push(frame);
+}
+
+/// Feeds a replay back in, frame by frame.
+pub struct ReplayPlayer {
+    pub replay: Replay,
+    next: usize,
+}
+
+impl ReplayPlayer {
+    pub fn new(replay: Replay) -> Self {
+        Self { replay, next: 0 }
+    }
+
+    pub fn finished(&self) -> bool {
+        self.next >= self.replay.frames.len()
+    }
+
+    fn next_frame(&mut self) -> Option<ReplayFrame> {
+        let frame = self.replay.frames.get(self.next).copied();
+        self.next += 1;
+        frame
+    }
+}
+
+/// Takes the place of `time_system` and `keyboard_system` during playback.
+/// Once the replay runs out time stops and no keys are held.
+pub fn playback_system(world: &mut World) {
+    let frame = world
+        .resource_mut::<ReplayPlayer>()
+        .next_frame()
+        .unwrap_or(ReplayFrame {
+            delta: 0.0,
+            input: InputState::default(),
+        });
+    world.resource_mut::<Time>().advance(frame.delta);
+    *world.resource_mut::<InputState>() = frame.input;
+}
+
+/// Plays the replay without a window and checks the world ends up the way
+/// it did when it was recorded. Returns the checksum.
+pub fn verify(replay: &Replay) -> Result<u64, Box<dyn Error>> {
+    let expected = replay.checksum.ok_or("replay has no checksum")?;
+    let game = HeadlessGame::play(replay)?;
+    let checksum = game.world.checksum()?;
+
+    if checksum != expected {
+        return Err(format!(
+            "world checksum {} doesn't match the recorded {}",
+            checksum, expected
+        )
+        .into());
+    }
+    Ok(checksum)
+}
@@ -1,123 +1,439 @@
-use std::{collections::HashMap, error::Error};
+use std::{collections::HashMap, error::Error, fs};
 
 use include_dir::{include_dir, Dir};
 use macroquad::{
     camera::Camera2D,
     file::load_string,
-    math::{Rect, Vec2},
+    math::Vec2,
+    miniquad::{RawId, TextureId},
     texture::{load_texture, FilterMode, Texture2D},
     window::{screen_height, screen_width},
 };
 use macroquad_tiled::Map;
+use nanoserde::{DeJson, SerJson};
 
-use crate::world::{self, World};
+use rand::{RngCore, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+use crate::{
+    components::{
+        enemy::EnemyDefinition,
+        rng::{stream_key, RngStream},
+    },
+    render::DrawQueue,
+    save::{SaveSlot, SAVE_FILE},
+    spatial::{self, SpatialIndex},
+    world::World,
+};
 
 static CORE_ASSETS_DIR: Dir = include_dir!("$CARGO_MANIFEST_DIR/assets");
+const ENEMIES_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/assets/enemies");
+
+/// Textures loaded at startup, keyed by their path under `assets/`.
+pub struct Assets {
+    pub core: HashMap<String, Texture2D>,
+    pub content: HashMap<String, Texture2D>,
+}
+
+impl Assets {
+    pub fn texture(&self, path: &str) -> Option<&Texture2D> {
+        self.core.get(path).or_else(|| self.content.get(path))
+    }
+
+    /// Path a texture was loaded from, used to write textures to save files.
+    pub fn path_of(&self, texture: &Texture2D) -> Option<&str> {
+        self.core
+            .iter()
+            .chain(&self.content)
+            .find(|(_, other)| *other == texture)
+            .map(|(path, _)| path.as_str())
+    }
+}
+
+/// Frame timing, written once per frame by `time_system`.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Time {
+    pub delta: f32,
+    pub elapsed: f64,
+}
+
+impl Time {
+    pub fn advance(&mut self, delta: f32) {
+        self.delta = delta;
+        self.elapsed += delta as f64;
+    }
+}
+
+/// Drives the FixedUpdate stage: gameplay runs in steps of `step` seconds,
+/// as many as fit in the time that passed, but never more than `max_steps`
+/// per frame so a long hitch doesn't snowball.
+#[derive(Debug, Clone, Copy)]
+pub struct FixedTime {
+    pub step: f32,
+    pub max_steps: u32,
+    accumulator: f32,
+}
+
+impl Default for FixedTime {
+    fn default() -> Self {
+        Self::new(60.0, 5)
+    }
+}
+
+impl FixedTime {
+    pub fn new(hz: f32, max_steps: u32) -> Self {
+        Self {
+            step: 1.0 / hz,
+            max_steps,
+            accumulator: 0.0,
+        }
+    }
+
+    /// Adds a frame's time and returns how many steps to run for it. Time
+    /// past `max_steps` is dropped.
+    pub fn expend(&mut self, delta: f32) -> u32 {
+        self.accumulator += delta;
+
+        let mut steps = 0;
+        while self.accumulator >= self.step && steps < self.max_steps {
+            self.accumulator -= self.step;
+            steps += 1;
+        }
+        if steps == self.max_steps {
+            self.accumulator %= self.step;
+        }
+        steps
+    }
+
+    /// How far the current frame is between the last step and the next one,
+    /// used to interpolate positions.
+    pub fn alpha(&self) -> f32 {
+        self.accumulator / self.step
+    }
+}
+
+/// Keys the game cares about, sampled once per frame by `keyboard_system`.
+#[derive(Debug, Default, Clone, Copy, SerJson, DeJson)]
+pub struct InputState {
+    pub up: bool,
+    pub down: bool,
+    pub left: bool,
+    pub right: bool,
+    pub attack: bool,
+    pub save: bool,
+    pub load: bool,
+    pub inspector: bool,
+}
+
+/// Enemy definitions by name, one per file in `assets/enemies/`.
+#[derive(Debug, Default)]
+pub struct EnemyRegistry {
+    definitions: HashMap<String, EnemyDefinition>,
+}
+
+impl EnemyRegistry {
+    pub fn get(&self, name: &str) -> Option<&EnemyDefinition> {
+        self.definitions.get(name)
+    }
+
+    pub fn insert(&mut self, name: &str, definition: EnemyDefinition) {
+        self.definitions.insert(name.to_string(), definition);
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.definitions.keys().map(String::as_str)
+    }
+}
+
+/// All gameplay randomness comes from here, so a run can be reproduced from
+/// its seed. Entities that roll dice get their own `RngStream` instead of
+/// drawing from the shared one, see `stream`.
+pub struct GameRng {
+    seed: u64,
+    rng: ChaCha8Rng,
+    spawns: HashMap<u64, u64>,
+}
+
+impl GameRng {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            rng: ChaCha8Rng::seed_from_u64(seed),
+            spawns: HashMap::new(),
+        }
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Independent stream for `key`. Same seed and key, same numbers, no
+    /// matter what else has been drawn.
+    pub fn stream(&self, key: u64) -> RngStream {
+        RngStream::new(self.seed, key)
+    }
 
-pub struct Resources {
-    pub tiled_map: Map,
-    pub camera: Camera2D,
-    core_assets: HashMap<String, Texture2D>,
-}
-
-impl Resources {
-    async fn new(world: &mut World) -> Result<Self, Box<dyn Error>> {
-        let core_assets = Self::load_core_assets("images/core/*.png").await?;
-        let tiled_map = Self::load_map(world, &core_assets).await?;
-        let camera = Camera2D {
-            zoom: Vec2::new(5.0 / screen_width(), 5.0 / screen_height()),
-            ..Default::default()
-        };
-        Ok(Self {
-            tiled_map,
-            core_assets,
-            camera,
-        })
-    }
-
-    pub async fn load_all(world: &mut World) -> Result<Resources, Box<dyn Error>> {
-        let resources = Self::new(world).await?;
-        // storage::store(resources);
-        Ok(resources)
-    }
-
-    async fn load_map(
-        world: &mut World,
-        core_assets: &HashMap<String, Texture2D>,
-    ) -> Result<Map, Box<dyn Error>> {
-        let tiled_map_json = load_string("map.json").await?;
-        let map = macroquad_tiled::load_map(
-            &tiled_map_json,
-            &[
-                (
-                    "images/core/decor.png",
-                    core_assets["images/core/decor.png"].clone(),
-                ),
-                (
-                    "images/core/grass.png",
-                    core_assets["images/core/grass.png"].clone(),
-                ),
-                (
-                    "images/core/objects.png",
-                    core_assets["images/core/objects.png"].clone(),
-                ),
-                (
-                    "images/core/plains.png",
-                    core_assets["images/core/plains.png"].clone(),
-                ),
-                (
-                    "images/core/rock_in_water.png",
-                    core_assets["images/core/rock_in_water.png"].clone(),
-                ),
-                (
-                    "images/core/water_decorations.png",
-                    core_assets["images/core/water_decorations.png"].clone(),
-                ),
-                (
-                    "images/core/water-sheet2.png",
-                    core_assets["images/core/water-sheet2.png"].clone(),
-                ),
-                (
-                    "images/core/wooden_door.png",
-                    core_assets["images/core/wooden_door.png"].clone(),
-                ),
-                (
-                    "images/core/walls.png",
-                    core_assets["images/core/walls.png"].clone(),
-                ),
-                (
-                    "images/core/wooden.png",
-                    core_assets["images/core/wooden.png"].clone(),
-                ),
-            ],
-            &[],
-        )?;
-
-        // get object layer -> object layer is for decorations (animated sprite)
-        let object_layer = map
-            .layers
-            .get("objects")
-            .ok_or_else(|| "Layer 'objects' not found")?;
-
-        // iterate through all the objects and add entity and components
-        for object in &object_layer.objects {
-            world.add_object(&object, &core_assets)?;
+    /// A fresh stream for every call with the same `key`: the first one is
+    /// `stream(key)`, later ones mix in how many came before. For things
+    /// spawned repeatedly from the same spot.
+    pub fn spawn_stream(&mut self, key: u64) -> RngStream {
+        let count = self.spawns.entry(key).or_default();
+        let spawned = *count;
+        *count += 1;
+
+        match spawned {
+            0 => self.stream(key),
+            n => RngStream::new(
+                self.seed,
+                stream_key(&[&key.to_le_bytes(), &n.to_le_bytes()]),
+            ),
         }
+    }
+
+    /// How many `spawn_stream`s each key handed out, sorted by key so saves
+    /// come out the same every time.
+    pub fn spawn_counts(&self) -> Vec<(u64, u64)> {
+        let mut counts: Vec<(u64, u64)> = self
+            .spawns
+            .iter()
+            .map(|(&key, &count)| (key, count))
+            .collect();
+        counts.sort_unstable();
+        counts
+    }
+
+    /// Picks the spawn counts back up from a save.
+    pub fn set_spawn_counts(&mut self, counts: &[(u64, u64)]) {
+        self.spawns = counts.iter().copied().collect();
+    }
+}
+
+impl RngCore for GameRng {
+    fn next_u32(&mut self) -> u32 {
+        self.rng.next_u32()
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.rng.next_u64()
+    }
+
+    fn fill_bytes(&mut self, dst: &mut [u8]) {
+        self.rng.fill_bytes(dst)
+    }
+}
+
+/// Loads every asset and inserts the global resources into the world.
+pub async fn load_all(world: &mut World, seed: u64) -> Result<(), Box<dyn Error>> {
+    let core = load_textures("images/core/*.png").await?;
+    let content = load_textures("images/content/*.png").await?;
+    let mut assets = Assets { core, content };
+
+    let enemies = load_enemies()?;
+    for definition in enemies.definitions.values() {
+        if assets.texture(&definition.texture).is_none() {
+            let texture = load_and_set_filter(&definition.texture).await?;
+            assets.content.insert(definition.texture.clone(), texture);
+        }
+    }
+
+    let camera = Camera2D {
+        zoom: Vec2::new(5.0 / screen_width(), 5.0 / screen_height()),
+        ..Default::default()
+    };
+    let tiled_map_json = load_string("map.json").await?;
+
+    insert_all(world, assets, enemies, &tiled_map_json, camera, seed)
+}
+
+/// Same as `load_all` without a window. Textures are placeholders that
+/// can't be drawn but still tell sprites apart, the map comes from the
+/// embedded assets.
+pub fn load_headless(world: &mut World, seed: u64) -> Result<(), Box<dyn Error>> {
+    let mut next_id = 0;
+    let core = placeholder_textures("images/core/*.png", &mut next_id)?;
+    let content = placeholder_textures("images/content/*.png", &mut next_id)?;
+    let mut assets = Assets { core, content };
+
+    let enemies = load_enemies()?;
+    for definition in enemies.definitions.values() {
+        if assets.texture(&definition.texture).is_none() {
+            next_id += 1;
+            assets
+                .content
+                .insert(definition.texture.clone(), placeholder_texture(next_id));
+        }
+    }
 
-        Ok(map)
+    let tiled_map_json = CORE_ASSETS_DIR
+        .get_file("map.json")
+        .and_then(|file| file.contents_utf8())
+        .ok_or("map.json not found in the embedded assets")?;
+
+    insert_all(
+        world,
+        assets,
+        enemies,
+        tiled_map_json,
+        Camera2D::default(),
+        seed,
+    )?;
+    // never touch the player's save file
+    world.insert_resource(SaveSlot::Memory(None));
+    Ok(())
+}
+
+fn insert_all(
+    world: &mut World,
+    assets: Assets,
+    enemies: EnemyRegistry,
+    tiled_map_json: &str,
+    camera: Camera2D,
+    seed: u64,
+) -> Result<(), Box<dyn Error>> {
+    let tiled_map = load_map(world, &assets.core, tiled_map_json)?;
+
+    world.insert_resource(SpatialIndex::from_map(&tiled_map, spatial::CELL_SIZE));
+    world.insert_resource(tiled_map);
+    world.insert_resource(camera);
+    world.insert_resource(assets);
+    world.insert_resource(enemies);
+    world.insert_resource(Time::default());
+    world.insert_resource(FixedTime::default());
+    world.insert_resource(InputState::default());
+    world.insert_resource(GameRng::new(seed));
+    world.insert_resource(DrawQueue::default());
+    world.insert_resource(SaveSlot::File(SAVE_FILE.to_string()));
+
+    Ok(())
+}
+
+fn load_map(
+    world: &mut World,
+    core_assets: &HashMap<String, Texture2D>,
+    tiled_map_json: &str,
+) -> Result<Map, Box<dyn Error>> {
+    let map = macroquad_tiled::load_map(
+        tiled_map_json,
+        &[
+            (
+                "images/core/decor.png",
+                core_assets["images/core/decor.png"].clone(),
+            ),
+            (
+                "images/core/grass.png",
+                core_assets["images/core/grass.png"].clone(),
+            ),
+            (
+                "images/core/objects.png",
+                core_assets["images/core/objects.png"].clone(),
+            ),
+            (
+                "images/core/plains.png",
+                core_assets["images/core/plains.png"].clone(),
+            ),
+            (
+                "images/core/rock_in_water.png",
+                core_assets["images/core/rock_in_water.png"].clone(),
+            ),
+            (
+                "images/core/water_decorations.png",
+                core_assets["images/core/water_decorations.png"].clone(),
+            ),
+            (
+                "images/core/water-sheet2.png",
+                core_assets["images/core/water-sheet2.png"].clone(),
+            ),
+            (
+                "images/core/wooden_door.png",
+                core_assets["images/core/wooden_door.png"].clone(),
+            ),
+            (
+                "images/core/walls.png",
+                core_assets["images/core/walls.png"].clone(),
+            ),
+            (
+                "images/core/wooden.png",
+                core_assets["images/core/wooden.png"].clone(),
+            ),
+        ],
+        &[],
+    )?;
+
+    // get object layer -> object layer is for decorations (animated sprite)
+    let object_layer = map
+        .layers
+        .get("objects")
+        .ok_or("Layer 'objects' not found")?;
+
+    // iterate through all the objects and add entity and components
+    for object in &object_layer.objects {
+        world.add_object(object, core_assets)?;
     }
 
-    async fn load_core_assets(path: &str) -> Result<HashMap<String, Texture2D>, Box<dyn Error>> {
-        let mut textures_map = HashMap::new();
+    Ok(map)
+}
+
+/// Reads the enemy definitions from disk rather than the embedded assets, so
+/// a new file is picked up without recompiling. The textures they point at
+/// are loaded by the caller. The directory is the crate's `assets/enemies`,
+/// wherever the game or a test is started from.
+fn load_enemies() -> Result<EnemyRegistry, Box<dyn Error>> {
+    let mut registry = EnemyRegistry::default();
 
-        for entry in CORE_ASSETS_DIR.find(path)? {
-            let asset_path = entry.path().to_string_lossy().to_string();
-            let loaded_texture = load_and_set_filter(&asset_path).await?;
-            textures_map.insert(asset_path, loaded_texture);
+    let entries = fs::read_dir(ENEMIES_DIR).map_err(|e| format!("{}: {}", ENEMIES_DIR, e))?;
+    for entry in entries {
+        let path = entry?.path();
+        if path.extension().is_none_or(|extension| extension != "json") {
+            continue;
         }
+        let name = path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .ok_or_else(|| format!("bad enemy file name {:?}", path))?;
+
+        let definition = EnemyDefinition::deserialize_json(&fs::read_to_string(&path)?)
+            .map_err(|e| format!("{}: {}", path.display(), e))?;
+        definition
+            .validate()
+            .map_err(|e| format!("{}: {}", path.display(), e))?;
 
-        Ok(textures_map)
+        registry.insert(name, definition);
     }
+
+    Ok(registry)
+}
+
+async fn load_textures(path: &str) -> Result<HashMap<String, Texture2D>, Box<dyn Error>> {
+    let mut textures_map = HashMap::new();
+
+    for entry in CORE_ASSETS_DIR.find(path)? {
+        let asset_path = entry.path().to_string_lossy().to_string();
+        let loaded_texture = load_and_set_filter(&asset_path).await?;
+        textures_map.insert(asset_path, loaded_texture);
+    }
+
+    Ok(textures_map)
+}
+
+fn placeholder_textures(
+    path: &str,
+    next_id: &mut u32,
+) -> Result<HashMap<String, Texture2D>, Box<dyn Error>> {
+    let mut textures_map = HashMap::new();
+
+    for entry in CORE_ASSETS_DIR.find(path)? {
+        *next_id += 1;
+        let asset_path = entry.path().to_string_lossy().to_string();
+        textures_map.insert(asset_path, placeholder_texture(*next_id));
+    }
+
+    Ok(textures_map)
+}
+
+/// A texture handle that was never uploaded, so it needs no GL context.
+/// Drawing it is undefined, it's only there to be compared and cloned.
+fn placeholder_texture(id: u32) -> Texture2D {
+    Texture2D::from_miniquad_texture(TextureId::from_raw_id(RawId::OpenGl(id)))
 }
 
 pub async fn load_and_set_filter(path: &str) -> Result<Texture2D, Box<dyn Error>> {
@@ -0,0 +1,521 @@
+// nanoserde's DeJson derive trips this on Option fields
+#![allow(clippy::question_mark)]
+
+use std::{collections::HashMap, error::Error, fmt, fs};
+
+use macroquad::math::{Rect, Vec2};
+use nanoserde::{DeJson, SerJson};
+
+use crate::{
+    components::{
+        collider::{Collider, ColliderData},
+        contact::Contact,
+        enemy::Enemy,
+        health::Health,
+        name::Name,
+        player::Player,
+        position::{LocalPosition, Position, PreviousPosition},
+        rng::RngStream,
+        sprite::{Sprite, SpriteSheet},
+        velocity::Velocity,
+    },
+    entity::entity::Entity,
+    resources::{Assets, GameRng},
+    world::World,
+};
+
+const SAVE_VERSION: u32 = 1;
+
+pub const SAVE_FILE: &str = "save.json";
+
+/// Where F5 saves to and F9 loads from. The game uses a file, replays and
+/// headless runs keep the save in memory so they never touch the player's.
+#[derive(Debug, Clone)]
+pub enum SaveSlot {
+    File(String),
+    Memory(Option<String>),
+}
+
+impl SaveSlot {
+    pub fn save(&mut self, world: &World) -> Result<(), Box<dyn Error>> {
+        match self {
+            SaveSlot::File(path) => save_world(world, path),
+            SaveSlot::Memory(save) => {
+                *save = Some(snapshot_json(world)?);
+                Ok(())
+            }
+        }
+    }
+
+    pub fn load(&self, world: &mut World) -> Result<(), Box<dyn Error>> {
+        load_json(world, &self.contents()?)
+    }
+
+    /// What a load would read right now.
+    pub fn contents(&self) -> Result<String, Box<dyn Error>> {
+        match self {
+            SaveSlot::File(path) => {
+                Ok(fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?)
+            }
+            SaveSlot::Memory(save) => Ok(save.clone().ok_or("nothing saved yet")?),
+        }
+    }
+}
+
+impl fmt::Display for SaveSlot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SaveSlot::File(path) => write!(f, "{}", path),
+            SaveSlot::Memory(_) => write!(f, "memory"),
+        }
+    }
+}
+
+/// Converts a component to and from what goes into a save file. Most
+/// components are plain data and store themselves, the ones holding
+/// textures or macroquad types go through a data struct.
+pub trait Persist: Sized + 'static {
+    type Data: SerJson + DeJson;
+
+    fn save(&self, world: &World) -> Result<Self::Data, Box<dyn Error>>;
+    fn load(data: Self::Data, world: &World) -> Result<Self, Box<dyn Error>>;
+}
+
+macro_rules! persist_as_is {
+    ($($ty:ty),+) => {
+        $(
+            impl Persist for $ty {
+                type Data = $ty;
+
+                fn save(&self, _world: &World) -> Result<Self::Data, Box<dyn Error>> {
+                    Ok(self.clone())
+                }
+
+                fn load(data: Self::Data, _world: &World) -> Result<Self, Box<dyn Error>> {
+                    Ok(data)
+                }
+            }
+        )+
+    };
+}
+
+persist_as_is!(
+    Position,
+    LocalPosition,
+    PreviousPosition,
+    Velocity,
+    Player,
+    Enemy,
+    Health,
+    Name,
+    Contact
+);
+
+impl Persist for Collider {
+    type Data = ColliderData;
+
+    fn save(&self, _world: &World) -> Result<Self::Data, Box<dyn Error>> {
+        Ok(self.into())
+    }
+
+    fn load(data: Self::Data, _world: &World) -> Result<Self, Box<dyn Error>> {
+        Ok((&data).into())
+    }
+}
+
+/// The stream position is a u128, which nanoserde doesn't do, so it's split
+/// in two.
+#[derive(SerJson, DeJson)]
+pub struct RngStreamData {
+    pub seed: u64,
+    pub key: u64,
+    pub position_high: u64,
+    pub position_low: u64,
+}
+
+impl Persist for RngStream {
+    type Data = RngStreamData;
+
+    fn save(&self, _world: &World) -> Result<Self::Data, Box<dyn Error>> {
+        let position = self.position();
+        Ok(RngStreamData {
+            seed: self.seed,
+            key: self.key,
+            position_high: (position >> 64) as u64,
+            position_low: position as u64,
+        })
+    }
+
+    fn load(data: Self::Data, _world: &World) -> Result<Self, Box<dyn Error>> {
+        let mut stream = RngStream::new(data.seed, data.key);
+        stream.set_position((data.position_high as u128) << 64 | data.position_low as u128);
+        Ok(stream)
+    }
+}
+
+/// Sprites store the asset path of their texture and rebuild the animation
+/// from its sheet, at the frame it was on.
+#[derive(SerJson, DeJson)]
+pub struct SpriteData {
+    pub texture: String,
+    pub source_rect: Option<[f32; 4]>,
+    pub dest_size: Option<[f32; 2]>,
+    pub flipped: bool,
+    pub last_animation: usize,
+    pub sheet: Option<SpriteSheet>,
+    pub animation: Option<AnimationState>,
+}
+
+#[derive(SerJson, DeJson)]
+pub struct AnimationState {
+    pub current: usize,
+    pub frame: u32,
+    pub playing: bool,
+    #[nserde(default)]
+    pub timer: f32,
+}
+
+impl Persist for Sprite {
+    type Data = SpriteData;
+
+    fn save(&self, world: &World) -> Result<Self::Data, Box<dyn Error>> {
+        let texture = world
+            .resource::<Assets>()
+            .path_of(&self.texture)
+            .ok_or("sprite texture isn't one of the loaded assets")?;
+
+        // the sheet is needed to rebuild the animation, without it the
+        // sprite comes back static
+        let animation = self
+            .animation
+            .as_ref()
+            .filter(|_| self.sheet.is_some())
+            .map(|animation| {
+                let source = animation.frame().source_rect;
+                AnimationState {
+                    current: animation.current_animation(),
+                    frame: (source.x / source.w) as u32,
+                    playing: animation.playing,
+                    timer: self.frame_timer,
+                }
+            });
+
+        Ok(SpriteData {
+            texture: texture.to_string(),
+            source_rect: self
+                .source_rect
+                .map(|rect| [rect.x, rect.y, rect.w, rect.h]),
+            dest_size: self.dest_size.map(Into::into),
+            flipped: self.flipped,
+            last_animation: self.last_animation,
+            sheet: self.sheet.clone(),
+            animation,
+        })
+    }
+
+    fn load(data: Self::Data, world: &World) -> Result<Self, Box<dyn Error>> {
+        let texture = world
+            .resource::<Assets>()
+            .texture(&data.texture)
+            .ok_or_else(|| format!("texture '{}' not loaded", data.texture))?
+            .clone();
+
+        let animation = match (&data.sheet, &data.animation) {
+            (Some(sheet), Some(state)) => {
+                let mut animation = sheet.animated_sprite();
+                animation.set_animation(state.current);
+                animation.set_frame(state.frame);
+                animation.playing = state.playing;
+                Some(animation)
+            }
+            _ => None,
+        };
+
+        Ok(Sprite {
+            texture,
+            source_rect: data.source_rect.map(|[x, y, w, h]| Rect::new(x, y, w, h)),
+            animation,
+            sheet: data.sheet,
+            dest_size: data.dest_size.map(Vec2::from),
+            flipped: data.flipped,
+            last_animation: data.last_animation,
+            frame_timer: data.animation.as_ref().map_or(0.0, |state| state.timer),
+        })
+    }
+}
+
+type SaveFn = fn(&World, Entity) -> Result<Option<String>, Box<dyn Error>>;
+type InsertComponent = Box<dyn FnOnce(&mut World, Entity)>;
+/// Parses a component, the returned closure adds it to an entity.
+type LoadFn = fn(&World, &str) -> Result<InsertComponent, Box<dyn Error>>;
+
+struct PersistentType {
+    key: &'static str,
+    save: SaveFn,
+    load: LoadFn,
+}
+
+/// Component types that go into save files, keyed by a name that has to stay
+/// stable between versions. Stored as a world resource, fill it with
+/// `World::register_persistent`.
+#[derive(Default)]
+pub struct SaveRegistry {
+    types: Vec<PersistentType>,
+}
+
+impl SaveRegistry {
+    pub fn register<T: Persist>(&mut self, key: &'static str) {
+        if self.types.iter().any(|persistent| persistent.key == key) {
+            return;
+        }
+
+        self.types.push(PersistentType {
+            key,
+            save: |world, entity| {
+                world
+                    .get_component::<T>(entity)
+                    .map(|component| Ok(component.save(world)?.serialize_json()))
+                    .transpose()
+            },
+            load: |world, json| {
+                let component = T::load(T::Data::deserialize_json(json)?, world)?;
+                Ok(Box::new(move |world: &mut World, entity| {
+                    world.add_component_to_entity(entity, component)
+                }))
+            },
+        });
+    }
+
+    fn get(&self, key: &str) -> Option<&PersistentType> {
+        self.types.iter().find(|persistent| persistent.key == key)
+    }
+}
+
+#[derive(SerJson, DeJson)]
+struct WorldSnapshot {
+    version: u32,
+    entities: Vec<EntitySnapshot>,
+    /// `GameRng::spawn_counts`, so enemies spawned after a load get the
+    /// streams they would have without it.
+    #[nserde(default)]
+    spawns: Vec<(u64, u64)>,
+}
+
+#[derive(SerJson, DeJson)]
+struct EntitySnapshot {
+    parent: Option<usize>, // index in `entities`
+    tiled_id: Option<u32>,
+    components: Vec<(String, String)>, // registry key -> component json
+}
+
+pub(crate) fn save_world(world: &World, path: &str) -> Result<(), Box<dyn Error>> {
+    fs::write(path, snapshot_json(world)?)?;
+    Ok(())
+}
+
+/// The save file contents of the world as it is now.
+pub(crate) fn snapshot_json(world: &World) -> Result<String, Box<dyn Error>> {
+    let registry = world
+        .get_resource::<SaveRegistry>()
+        .ok_or("no persistent components registered")?;
+
+    let entities: Vec<Entity> = world.entities().collect();
+    let indices: HashMap<Entity, usize> = entities
+        .iter()
+        .enumerate()
+        .map(|(index, &entity)| (entity, index))
+        .collect();
+
+    let mut snapshot = WorldSnapshot {
+        version: SAVE_VERSION,
+        entities: Vec::with_capacity(entities.len()),
+        spawns: world
+            .get_resource::<GameRng>()
+            .map_or_else(Vec::new, GameRng::spawn_counts),
+    };
+
+    for &entity in &entities {
+        let mut components = Vec::new();
+        for persistent in &registry.types {
+            if let Some(json) = (persistent.save)(world, entity)? {
+                components.push((persistent.key.to_string(), json));
+            }
+        }
+
+        snapshot.entities.push(EntitySnapshot {
+            parent: world
+                .parent(entity)
+                .and_then(|parent| indices.get(&parent).copied()),
+            tiled_id: world.tiled_object_id(entity),
+            components,
+        });
+    }
+
+    Ok(snapshot.serialize_json())
+}
+
+/// Replaces every entity in the world with the ones in the save. Resources
+/// stay as they are, apart from the `GameRng` spawn counts. Every component is parsed before anything is
+/// despawned, so a bad save leaves the world alone.
+pub(crate) fn load_world(world: &mut World, path: &str) -> Result<(), Box<dyn Error>> {
+    load_json(world, &fs::read_to_string(path)?)
+}
+
+/// `load_world` from the save file contents.
+pub(crate) fn load_json(world: &mut World, json: &str) -> Result<(), Box<dyn Error>> {
+    let snapshot = WorldSnapshot::deserialize_json(json)?;
+    if snapshot.version != SAVE_VERSION {
+        return Err(format!("unsupported save version {}", snapshot.version).into());
+    }
+    if !world.has_resource::<SaveRegistry>() {
+        return Err("no persistent components registered".into());
+    }
+
+    world.resource_scope::<SaveRegistry, _>(|world, registry| {
+        let mut staged = Vec::with_capacity(snapshot.entities.len());
+        for saved in &snapshot.entities {
+            let components = saved
+                .components
+                .iter()
+                .map(|(key, json)| {
+                    let persistent = registry
+                        .get(key)
+                        .ok_or_else(|| format!("unknown component '{}' in save", key))?;
+                    (persistent.load)(world, json).map_err(|e| format!("{}: {}", key, e).into())
+                })
+                .collect::<Result<Vec<_>, Box<dyn Error>>>()?;
+            staged.push(components);
+        }
+
+        let old: Vec<Entity> = world.entities().collect();
+        for entity in old {
+            world.despawn(entity);
+        }
+
+        let mut spawned = Vec::with_capacity(snapshot.entities.len());
+        for (saved, components) in snapshot.entities.iter().zip(staged) {
+            let entity = world.add_entity();
+            if let Some(id) = saved.tiled_id {
+                world.map_tiled_object(id, entity);
+            }
+            for insert in components {
+                insert(world, entity);
+            }
+            spawned.push(entity);
+        }
+        if let Some(rng) = world.get_resource_mut::<GameRng>() {
+            rng.set_spawn_counts(&snapshot.spawns);
+        }
+
+        for (saved, &entity) in snapshot.entities.iter().zip(&spawned) {
+            if let Some(&parent) = saved.parent.and_then(|index| spawned.get(index)) {
+                world.set_parent(entity, parent);
+            }
+        }
+
+        Ok(())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SaveSlot;
+    use crate::{
+        components::{
+            enemy::{AIType, Enemy},
+            health::Health,
+            name::Name,
+            player::Player,
+            position::Position,
+            rng::RngStream,
+        },
+        entity::entity::Entity,
+        headless::HeadlessGame,
+        world::World,
+    };
+
+    #[test]
+    fn memory_slot_round_trip() {
+        let mut game = HeadlessGame::new(1).unwrap();
+        let world = &mut game.world;
+        let player = world.spawn_player(100.0, 120.0).unwrap();
+        let skeleton = world.spawn_enemy(300.0, 300.0, "skeleton").unwrap();
+        world.get_component_mut::<Enemy>(skeleton).unwrap().ai_type = AIType::ChasePlayer;
+        world.get_component_mut::<Health>(skeleton).unwrap().current = 2;
+
+        let mut slot = SaveSlot::Memory(None);
+        slot.save(world).unwrap();
+
+        // change everything, then load it back
+        *world.get_component_mut::<Position>(player).unwrap() = Position { x: 0.0, y: 0.0 };
+        world.get_component_mut::<Enemy>(skeleton).unwrap().ai_type = AIType::Dead;
+        world.get_component_mut::<Health>(skeleton).unwrap().current = 0;
+        slot.load(world).unwrap();
+
+        let (position, _) = world.query::<(&Position, &Player)>().single();
+        assert_eq!((position.x, position.y), (100.0, 120.0));
+
+        let chasing: Vec<i32> = world
+            .query::<(&Enemy, &Health)>()
+            .filter(|(enemy, _)| matches!(enemy.ai_type, AIType::ChasePlayer))
+            .map(|(_, health)| health.current)
+            .collect();
+        assert_eq!(chasing, vec![2]);
+    }
+
+    #[test]
+    fn spawns_after_a_load_continue_from_the_save() {
+        let mut game = HeadlessGame::new(1).unwrap();
+        let world = &mut game.world;
+        world.spawn_enemy(300.0, 300.0, "skeleton").unwrap();
+
+        let mut slot = SaveSlot::Memory(None);
+        slot.save(world).unwrap();
+
+        let second = world.spawn_enemy(300.0, 300.0, "skeleton").unwrap();
+        let expected = world.get_component::<RngStream>(second).unwrap().key;
+        world.spawn_enemy(300.0, 300.0, "skeleton").unwrap();
+
+        // one skeleton had spawned there when saving, the next is the second
+        slot.load(world).unwrap();
+        let again = world.spawn_enemy(300.0, 300.0, "skeleton").unwrap();
+        assert_eq!(
+            world.get_component::<RngStream>(again).unwrap().key,
+            expected
+        );
+    }
+
+    #[test]
+    fn names_and_tiled_ids_survive_a_load() {
+        let mut game = HeadlessGame::new(1).unwrap();
+        let world = &mut game.world;
+        // one of the map's decorations
+        let (before, name) = world
+            .query::<(Entity, &Name)>()
+            .map(|(entity, name)| (entity, name.0.clone()))
+            .next()
+            .unwrap();
+        let id = world.tiled_object_id(before).unwrap();
+
+        let mut slot = SaveSlot::Memory(None);
+        slot.save(world).unwrap();
+        slot.load(world).unwrap();
+
+        let after = world.tiled_object(id).unwrap();
+        assert!(!world.is_alive(before));
+        assert_eq!(world.tiled_object_id(after), Some(id));
+        assert_eq!(world.get_component::<Name>(after).unwrap().0, name);
+        assert!(world.find_all_by_name(&name).contains(&after));
+        assert!(world
+            .find_all_by_name(&name)
+            .iter()
+            .all(|&entity| world.is_alive(entity)));
+    }
+
+    #[test]
+    fn load_without_registry_is_an_error() {
+        let mut world = World::new();
+        let slot = SaveSlot::Memory(Some(r#"{"version":1,"entities":[]}"#.to_string()));
+        assert!(slot.load(&mut world).is_err());
+    }
+}
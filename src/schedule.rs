@@ -0,0 +1,530 @@
+use std::any::type_name;
+
+use crate::{
+    parallel::{self, BatchSystem, SystemAccess, WorldCell},
+    pool::ThreadPool,
+    query::Fetch,
+    resources::{FixedTime, Time},
+    storage::{check_tick, CHECK_TICK_THRESHOLD},
+    world::World,
+};
+
+/// Stages run in this order every frame. Deferred commands are applied at the
+/// end of each stage, so the next one sees the spawns/despawns.
+///
+/// FixedUpdate runs zero or more times per frame, once per `FixedTime` step,
+/// with `Time::delta` set to the step. Without a `FixedTime` resource it runs
+/// once with the frame time like the others.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Stage {
+    PreUpdate,
+    FixedUpdate,
+    Update,
+    PostUpdate,
+    Render,
+}
+
+impl Stage {
+    pub const ALL: [Stage; 5] = [
+        Stage::PreUpdate,
+        Stage::FixedUpdate,
+        Stage::Update,
+        Stage::PostUpdate,
+        Stage::Render,
+    ];
+}
+
+enum SystemFn {
+    /// Runs alone on the main thread with the whole world.
+    Exclusive(Box<dyn FnMut(&mut World)>),
+    /// Only sees what it declared, and may run alongside other parallel
+    /// systems on the pool.
+    Parallel(Box<dyn FnMut(&mut WorldCell) + Send>, SystemAccess),
+}
+
+struct SystemEntry {
+    label: &'static str,
+    stage: Stage,
+    system: SystemFn,
+    /// Change tick of the system's last run, 0 before the first one.
+    last_run: u32,
+    before: Vec<&'static str>,
+    after: Vec<&'static str>,
+}
+
+/// Label a system is known by in `before`/`after`, the path of its fn.
+fn label_of<S>(_: &S) -> &'static str {
+    type_name::<S>()
+}
+
+impl SystemEntry {
+    fn ordered_with(&self, other: &SystemEntry) -> bool {
+        self.before.contains(&other.label)
+            || self.after.contains(&other.label)
+            || other.before.contains(&self.label)
+            || other.after.contains(&self.label)
+    }
+
+    fn access(&self) -> Option<&SystemAccess> {
+        match &self.system {
+            SystemFn::Exclusive(_) => None,
+            SystemFn::Parallel(_, access) => Some(access),
+        }
+    }
+
+    /// Only parallel systems can share a batch, exclusive ones run alone on
+    /// the main thread.
+    fn can_join(&self, batch: &[&SystemEntry]) -> bool {
+        let Some(access) = self.access() else {
+            return false;
+        };
+        batch.iter().all(|other| {
+            other
+                .access()
+                .is_some_and(|other_access| !access.conflicts_with(other_access))
+                && !self.ordered_with(other)
+        })
+    }
+}
+
+/// System functions of either kind, so `before`/`after` can name both.
+pub trait SystemLabel<Marker> {}
+
+impl<F: FnMut(&mut World)> SystemLabel<fn(&mut World)> for F {}
+
+impl<F: FnMut(&mut WorldCell)> SystemLabel<fn(&mut WorldCell)> for F {}
+
+/// Systems grouped by stage, run in an order that respects the declared
+/// `before`/`after` constraints. Without constraints systems run in the order
+/// they were added.
+///
+/// Neighbouring parallel systems that don't conflict run together on a pool
+/// of worker threads. Conflicting ones keep their order, so the result is
+/// the same as running everything in sequence.
+#[derive(Default)]
+pub struct Schedule {
+    systems: Vec<SystemEntry>,
+    plan: Option<Vec<Vec<usize>>>,
+    pool: ThreadPool,
+    /// Change tick of the last `World::check_change_ticks`.
+    last_check_tick: u32,
+}
+
+impl Schedule {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds an exclusive system to a stage, ordering can be chained on the
+    /// result: `schedule.add_system(Stage::Update, camera_systems).after(movement_systems)`.
+    /// It runs alone on the main thread with the whole world.
+    pub fn add_system<S: FnMut(&mut World) + 'static>(
+        &mut self,
+        stage: Stage,
+        system: S,
+    ) -> SystemConfig<'_> {
+        let label = label_of(&system);
+        self.push(label, stage, SystemFn::Exclusive(Box::new(system)))
+    }
+
+    /// Adds a system that only gets a `WorldCell` and has to declare what it
+    /// touches with `access`/`resource`/`resource_mut`. Parallel systems that
+    /// don't conflict may run at the same time on worker threads.
+    pub fn add_parallel_system<S: FnMut(&mut WorldCell) + Send + 'static>(
+        &mut self,
+        stage: Stage,
+        system: S,
+    ) -> SystemConfig<'_> {
+        let label = label_of(&system);
+        let system = SystemFn::Parallel(Box::new(system), SystemAccess::default());
+        self.push(label, stage, system)
+    }
+
+    fn push(&mut self, label: &'static str, stage: Stage, system: SystemFn) -> SystemConfig<'_> {
+        self.systems.push(SystemEntry {
+            label,
+            stage,
+            system,
+            last_run: 0,
+            before: Vec::new(),
+            after: Vec::new(),
+        });
+        self.plan = None;
+
+        let entry = self.systems.last_mut().unwrap();
+        SystemConfig { entry }
+    }
+
+    /// Runs every stage, then ends the frame: swaps the event buffers and
+    /// starts a new change detection window.
+    pub fn run(&mut self, world: &mut World) {
+        let plan = self.plan.take().unwrap_or_else(|| self.plan());
+
+        let mut start = 0;
+        while start < plan.len() {
+            let stage = self.systems[plan[start][0]].stage;
+            let end = plan[start..]
+                .iter()
+                .position(|batch| self.systems[batch[0]].stage != stage)
+                .map_or(plan.len(), |len| start + len);
+
+            if stage == Stage::FixedUpdate {
+                self.run_fixed(world, &plan[start..end]);
+            } else {
+                self.run_stage(world, &plan[start..end]);
+            }
+            start = end;
+        }
+
+        world.update_events();
+        world.clear_trackers();
+        self.check_change_ticks(world);
+        self.plan = Some(plan);
+    }
+
+    /// Clamps old ticks in the world and the systems' last runs before they
+    /// wrap around.
+    fn check_change_ticks(&mut self, world: &mut World) {
+        let change_tick = world.change_tick();
+        if change_tick.wrapping_sub(self.last_check_tick) < CHECK_TICK_THRESHOLD {
+            return;
+        }
+
+        world.check_change_ticks();
+        for system in &mut self.systems {
+            check_tick(&mut system.last_run, change_tick);
+        }
+        self.last_check_tick = change_tick;
+    }
+
+    fn run_stage(&mut self, world: &mut World, batches: &[Vec<usize>]) {
+        for batch in batches {
+            self.run_batch(world, batch);
+        }
+        // commands get their own tick, the last batch already ran at the
+        // current one and would miss them otherwise
+        world.increment_change_tick();
+        world.apply_commands();
+    }
+
+    fn run_fixed(&mut self, world: &mut World, batches: &[Vec<usize>]) {
+        let frame_delta = world.get_resource::<Time>().map_or(0.0, |time| time.delta);
+        let Some(fixed) = world.get_resource_mut::<FixedTime>() else {
+            self.run_stage(world, batches);
+            return;
+        };
+        let steps = fixed.expend(frame_delta);
+        let step = fixed.step;
+
+        if let Some(time) = world.get_resource_mut::<Time>() {
+            time.delta = step;
+        }
+        world.set_in_fixed_update(true);
+        for _ in 0..steps {
+            self.run_stage(world, batches);
+        }
+        world.set_in_fixed_update(false);
+        if let Some(time) = world.get_resource_mut::<Time>() {
+            time.delta = frame_delta;
+        }
+    }
+
+    /// Each batch runs at a new change tick, so its systems see what ran
+    /// before them and `Changed`/`Added` can compare against `last_run`.
+    fn run_batch(&mut self, world: &mut World, batch: &[usize]) {
+        let this_run = world.increment_change_tick();
+
+        if let [index] = batch
+            && let entry = &mut self.systems[*index]
+            && let SystemFn::Exclusive(system) = &mut entry.system
+        {
+            let last_change_tick = world.last_change_tick();
+            world.set_last_change_tick(entry.last_run);
+            system(world);
+            world.set_last_change_tick(last_change_tick);
+            entry.last_run = this_run;
+            return;
+        }
+
+        let mut entries: Vec<Option<&mut SystemEntry>> =
+            self.systems.iter_mut().map(Some).collect();
+        let batch = batch
+            .iter()
+            .filter_map(|&index| entries[index].take())
+            .map(|entry| match &mut entry.system {
+                SystemFn::Parallel(system, access) => BatchSystem {
+                    name: entry.label,
+                    access,
+                    last_run: std::mem::replace(&mut entry.last_run, this_run),
+                    system: system.as_mut(),
+                },
+                SystemFn::Exclusive(_) => unreachable!("exclusive systems run alone"),
+            })
+            .collect();
+        parallel::run_batch(world, &mut self.pool, batch);
+    }
+
+    /// Labels of the systems in the order they run.
+    pub fn system_order(&mut self) -> Vec<&'static str> {
+        self.batches().into_iter().flatten().collect()
+    }
+
+    /// Same as `system_order`, grouped by the batches that run at the same
+    /// time.
+    pub fn batches(&mut self) -> Vec<Vec<&'static str>> {
+        let plan = self.plan.take().unwrap_or_else(|| self.plan());
+        let labels = plan
+            .iter()
+            .map(|batch| batch.iter().map(|&i| self.systems[i].label).collect())
+            .collect();
+        self.plan = Some(plan);
+        labels
+    }
+
+    /// Splits the sorted systems into batches, never across stages. Each
+    /// system goes into the first batch after the last one it conflicts or
+    /// is ordered with, so conflicting systems still run in sorted order.
+    fn plan(&self) -> Vec<Vec<usize>> {
+        let mut plan: Vec<Vec<usize>> = Vec::new();
+        let mut stage_start = 0;
+
+        for index in self.sort() {
+            let entry = &self.systems[index];
+            if plan
+                .get(stage_start)
+                .is_some_and(|batch| self.systems[batch[0]].stage != entry.stage)
+            {
+                stage_start = plan.len();
+            }
+
+            let earliest = (stage_start..plan.len())
+                .rev()
+                .find(|&batch| {
+                    let others: Vec<&SystemEntry> =
+                        plan[batch].iter().map(|&i| &self.systems[i]).collect();
+                    !entry.can_join(&others)
+                })
+                .map_or(stage_start, |batch| batch + 1);
+
+            match plan.get_mut(earliest) {
+                Some(batch) => batch.push(index),
+                None => plan.push(vec![index]),
+            }
+        }
+
+        plan
+    }
+
+    /// Topological sort inside each stage. When several systems are free to
+    /// run the one added first goes, so the order is stable between runs.
+    fn sort(&self) -> Vec<usize> {
+        let mut order = Vec::with_capacity(self.systems.len());
+
+        for stage in Stage::ALL {
+            let in_stage: Vec<usize> = (0..self.systems.len())
+                .filter(|&i| self.systems[i].stage == stage)
+                .collect();
+
+            // edges[a] holds the systems that have to wait for a
+            let mut edges = vec![Vec::new(); in_stage.len()];
+            let mut waiting_on = vec![0; in_stage.len()];
+            let position = |label: &str| {
+                in_stage
+                    .iter()
+                    .position(|&i| self.systems[i].label == label)
+            };
+
+            for (a, &index) in in_stage.iter().enumerate() {
+                let entry = &self.systems[index];
+                for &label in &entry.before {
+                    if let Some(b) = position(label) {
+                        edges[a].push(b);
+                        waiting_on[b] += 1;
+                    }
+                }
+                for &label in &entry.after {
+                    if let Some(b) = position(label) {
+                        edges[b].push(a);
+                        waiting_on[a] += 1;
+                    }
+                }
+            }
+
+            let mut done = vec![false; in_stage.len()];
+            for _ in 0..in_stage.len() {
+                let Some(next) = (0..in_stage.len()).find(|&i| !done[i] && waiting_on[i] == 0)
+                else {
+                    let stuck: Vec<&str> = (0..in_stage.len())
+                        .filter(|&i| !done[i])
+                        .map(|i| self.systems[in_stage[i]].label)
+                        .collect();
+                    panic!("{:?} systems have cyclic ordering: {:?}", stage, stuck);
+                };
+
+                done[next] = true;
+                for &b in &edges[next] {
+                    waiting_on[b] -= 1;
+                }
+                order.push(in_stage[next]);
+            }
+        }
+
+        order
+    }
+}
+
+/// Returned by `Schedule::add_system` and `add_parallel_system` to declare
+/// ordering against other
+/// systems of the same stage. Constraints on systems in other stages or that
+/// were never added are ignored.
+pub struct SystemConfig<'a> {
+    entry: &'a mut SystemEntry,
+}
+
+impl SystemConfig<'_> {
+    pub fn before<M, S: SystemLabel<M> + 'static>(self, system: S) -> Self {
+        self.entry.before.push(label_of(&system));
+        self
+    }
+
+    pub fn after<M, S: SystemLabel<M> + 'static>(self, system: S) -> Self {
+        self.entry.after.push(label_of(&system));
+        self
+    }
+
+    /// Declares the components a parallel system queries, e.g.
+    /// `.access::<(&Position, &mut Enemy)>()`. Touching anything it didn't
+    /// declare panics. Components read need to be `Sync`, and written ones
+    /// `Send + Sync`, since the system may run on a worker thread.
+    ///
+    /// Systems calling into macroquad (drawing, input, camera) must stay
+    /// exclusive, since macroquad only works from the main thread.
+    pub fn access<Q: Fetch<'static> + Send + Sync>(mut self) -> Self {
+        self.declared().add_query::<Q>();
+        self
+    }
+
+    /// Declares a resource a parallel system reads.
+    pub fn resource<R: Send + Sync + 'static>(mut self) -> Self {
+        self.declared().add_resource::<R>(false);
+        self
+    }
+
+    /// Declares a resource a parallel system writes, event writers included
+    /// (`resource_mut::<Events<PlayerHit>>()`).
+    pub fn resource_mut<R: Send + Sync + 'static>(mut self) -> Self {
+        self.declared().add_resource::<R>(true);
+        self
+    }
+
+    /// Panics for exclusive systems, they have the whole world anyway.
+    fn declared(&mut self) -> &mut SystemAccess {
+        match &mut self.entry.system {
+            SystemFn::Parallel(_, access) => access,
+            SystemFn::Exclusive(_) => panic!(
+                "system {} is exclusive, add it with add_parallel_system to declare access",
+                self.entry.label
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Schedule, Stage};
+    use crate::{
+        entity::entity::Entity,
+        filter::Changed,
+        parallel::WorldCell,
+        resources::{FixedTime, Time},
+        world::World,
+    };
+
+    struct Counter(u32);
+    struct Write(bool);
+    struct Seen(Vec<usize>);
+
+    fn writer(world: &mut World) {
+        if world.resource::<Write>().0 {
+            for counter in world.query::<&mut Counter>() {
+                counter.0 += 1;
+            }
+        }
+    }
+
+    fn reader(world: &mut WorldCell) {
+        let changed = world.query_filtered::<Entity, Changed<Counter>>().count();
+        world.resource_mut::<Seen>().0.push(changed);
+    }
+
+    #[test]
+    fn systems_see_changes_made_after_them_next_frame() {
+        let mut world = World::new();
+        world.spawn_entity().with(Counter(0));
+        world.insert_resource(Seen(Vec::new()));
+
+        let mut schedule = Schedule::new();
+        schedule.add_system(Stage::Update, writer);
+        schedule
+            .add_parallel_system(Stage::Update, reader)
+            .before(writer)
+            .access::<&Counter>()
+            .resource_mut::<Seen>();
+
+        for write in [false, true, false, false] {
+            world.insert_resource(Write(write));
+            schedule.run(&mut world);
+        }
+
+        // the spawn, nothing yet, the write from the frame before, nothing
+        assert_eq!(world.resource::<Seen>().0, vec![1, 0, 1, 0]);
+    }
+
+    struct Tick;
+
+    fn tick(world: &mut World) {
+        world.event_writer::<Tick>().send(Tick);
+    }
+
+    fn count_ticks(world: &mut World) {
+        let ticks = world.event_reader::<Tick>().len();
+        world.resource_mut::<Seen>().0.push(ticks);
+    }
+
+    #[test]
+    fn fixed_update_events_are_read_once() {
+        let mut world = World::new();
+        world.add_event::<Tick>();
+        world.insert_resource(Seen(Vec::new()));
+        world.insert_resource(Time::default());
+        world.insert_resource(FixedTime::new(4.0, 5));
+
+        let mut schedule = Schedule::new();
+        schedule.add_system(Stage::FixedUpdate, tick);
+        schedule.add_system(Stage::Update, count_ticks);
+
+        // three steps, none, one, none
+        for delta in [0.75, 0.0, 0.25, 0.0] {
+            world.resource_mut::<Time>().advance(delta);
+            schedule.run(&mut world);
+        }
+
+        // each frame reads what the one before sent
+        assert_eq!(world.resource::<Seen>().0, vec![0, 3, 0, 1]);
+    }
+
+    #[test]
+    #[should_panic(expected = "FixedUpdate")]
+    fn reading_events_in_fixed_update_panics() {
+        let mut world = World::new();
+        world.add_event::<Tick>();
+        world.insert_resource(Seen(Vec::new()));
+        world.insert_resource(Time::default());
+        world.insert_resource(FixedTime::new(4.0, 5));
+
+        let mut schedule = Schedule::new();
+        schedule.add_system(Stage::FixedUpdate, count_ticks);
+        world.resource_mut::<Time>().advance(0.25);
+        schedule.run(&mut world);
+    }
+}
@@ -0,0 +1,316 @@
+use macroquad::math::{vec2, Rect, Vec2};
+use macroquad_tiled::{Object, ObjectShape};
+
+/// A map object's outline in world coordinates, rotation already applied.
+/// Rotated rects become polygons.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Shape {
+    Rect(Rect),
+    Ellipse {
+        center: Vec2,
+        radii: Vec2,
+        /// radians, clockwise
+        rotation: f32,
+    },
+    Polygon(Vec<Vec2>),
+    Polyline(Vec<Vec2>),
+    Point(Vec2),
+}
+
+impl Shape {
+    pub fn from_object(object: &Object) -> Self {
+        let origin = vec2(object.world_x, object.world_y);
+        let size = vec2(object.world_w, object.world_h);
+        let rotation = object.rotation.to_radians();
+        let place = |point: Vec2| origin + rotate(point, rotation);
+
+        match &object.shape {
+            ObjectShape::Rect if rotation == 0.0 => {
+                Shape::Rect(Rect::new(origin.x, origin.y, size.x, size.y))
+            }
+            ObjectShape::Rect => Shape::Polygon(
+                [vec2(0.0, 0.0), vec2(size.x, 0.0), size, vec2(0.0, size.y)]
+                    .into_iter()
+                    .map(place)
+                    .collect(),
+            ),
+            ObjectShape::Ellipse => Shape::Ellipse {
+                center: place(size / 2.0),
+                radii: size / 2.0,
+                rotation,
+            },
+            ObjectShape::Polygon(points) => {
+                Shape::Polygon(points.iter().copied().map(place).collect())
+            }
+            ObjectShape::Polyline(points) => {
+                Shape::Polyline(points.iter().copied().map(place).collect())
+            }
+            ObjectShape::Point => Shape::Point(origin),
+        }
+    }
+
+    /// Smallest axis aligned box around the shape.
+    pub fn bounds(&self) -> Rect {
+        match self {
+            Shape::Rect(rect) => *rect,
+            Shape::Ellipse {
+                center,
+                radii,
+                rotation,
+            } => {
+                let (sin, cos) = rotation.sin_cos();
+                let half = vec2(
+                    (radii.x * cos).hypot(radii.y * sin),
+                    (radii.x * sin).hypot(radii.y * cos),
+                );
+                Rect::new(
+                    center.x - half.x,
+                    center.y - half.y,
+                    half.x * 2.0,
+                    half.y * 2.0,
+                )
+            }
+            Shape::Polygon(points) | Shape::Polyline(points) => {
+                let Some(&first) = points.first() else {
+                    return Rect::default();
+                };
+                let (min, max) = points.iter().fold((first, first), |(min, max), &point| {
+                    (min.min(point), max.max(point))
+                });
+                Rect::new(min.x, min.y, max.x - min.x, max.y - min.y)
+            }
+            Shape::Point(point) => Rect::new(point.x, point.y, 0.0, 0.0),
+        }
+    }
+
+    /// Whether the shape touches `rect`, edges included like `Rect::overlaps`.
+    pub fn overlaps_rect(&self, rect: Rect) -> bool {
+        match self {
+            Shape::Rect(other) => other.overlaps(&rect),
+            Shape::Ellipse {
+                center,
+                radii,
+                rotation,
+            } => ellipse_overlaps_rect(*center, *radii, *rotation, rect),
+            Shape::Polygon(points) => {
+                // either an edge crosses the box, or the box is inside
+                edges(points, true).any(|(a, b)| segment_overlaps_rect(a, b, rect))
+                    || contains(points, rect.center())
+            }
+            Shape::Polyline(points) => match points.as_slice() {
+                [point] => rect_contains(rect, *point),
+                _ => edges(points, false).any(|(a, b)| segment_overlaps_rect(a, b, rect)),
+            },
+            Shape::Point(point) => rect_contains(rect, *point),
+        }
+    }
+}
+
+/// Clockwise in screen space, where y points down.
+fn rotate(point: Vec2, angle: f32) -> Vec2 {
+    let (sin, cos) = angle.sin_cos();
+    vec2(point.x * cos - point.y * sin, point.x * sin + point.y * cos)
+}
+
+fn rect_contains(rect: Rect, point: Vec2) -> bool {
+    point.x >= rect.left()
+        && point.x <= rect.right()
+        && point.y >= rect.top()
+        && point.y <= rect.bottom()
+}
+
+/// Consecutive point pairs, plus last to first when `closed`.
+fn edges(points: &[Vec2], closed: bool) -> impl Iterator<Item = (Vec2, Vec2)> + '_ {
+    let closing = match (closed, points) {
+        (true, [first, .., last]) => Some((*last, *first)),
+        _ => None,
+    };
+    points
+        .windows(2)
+        .map(|pair| (pair[0], pair[1]))
+        .chain(closing)
+}
+
+/// Even-odd rule, works for concave polygons too.
+fn contains(polygon: &[Vec2], point: Vec2) -> bool {
+    let mut inside = false;
+    for (a, b) in edges(polygon, true) {
+        if (a.y > point.y) != (b.y > point.y)
+            && point.x < a.x + (point.y - a.y) / (b.y - a.y) * (b.x - a.x)
+        {
+            inside = !inside;
+        }
+    }
+    inside
+}
+
+/// Liang-Barsky: clips the segment to the box and checks something is left.
+fn segment_overlaps_rect(a: Vec2, b: Vec2, rect: Rect) -> bool {
+    let delta = b - a;
+    let (mut enter, mut exit) = (0.0f32, 1.0f32);
+
+    for (direction, distance) in [
+        (-delta.x, a.x - rect.left()),
+        (delta.x, rect.right() - a.x),
+        (-delta.y, a.y - rect.top()),
+        (delta.y, rect.bottom() - a.y),
+    ] {
+        if direction == 0.0 {
+            // parallel to this side, outside of it means no overlap at all
+            if distance < 0.0 {
+                return false;
+            }
+        } else {
+            let t = distance / direction;
+            if direction < 0.0 {
+                enter = enter.max(t);
+            } else {
+                exit = exit.min(t);
+            }
+        }
+    }
+    enter <= exit
+}
+
+/// Maps the box into the space where the ellipse is the unit circle, where
+/// it becomes a parallelogram, and checks that against the circle.
+fn ellipse_overlaps_rect(center: Vec2, radii: Vec2, rotation: f32, rect: Rect) -> bool {
+    if radii.x <= 0.0 || radii.y <= 0.0 {
+        // flat ellipse, what's left of it is a line
+        let half = rotate(vec2(radii.x, radii.y), rotation);
+        return segment_overlaps_rect(center - half, center + half, rect);
+    }
+
+    let corners = [
+        vec2(rect.left(), rect.top()),
+        vec2(rect.right(), rect.top()),
+        vec2(rect.right(), rect.bottom()),
+        vec2(rect.left(), rect.bottom()),
+    ]
+    .map(|corner| rotate(corner - center, -rotation) / radii);
+
+    // same side of every edge, whichever way the corners wind
+    let (left, right) = edges(&corners, true)
+        .map(|(a, b)| (b - a).perp_dot(-a))
+        .fold((true, true), |(left, right), side| {
+            (left && side >= 0.0, right && side <= 0.0)
+        });
+
+    left || right
+        || edges(&corners, true).any(|(a, b)| distance_to_segment(Vec2::ZERO, a, b) <= 1.0)
+}
+
+fn distance_to_segment(point: Vec2, a: Vec2, b: Vec2) -> f32 {
+    let along = b - a;
+    let length = along.length_squared();
+    let t = if length == 0.0 {
+        0.0
+    } else {
+        ((point - a).dot(along) / length).clamp(0.0, 1.0)
+    };
+    point.distance(a + along * t)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use macroquad::math::{vec2, Rect, Vec2};
+    use macroquad_tiled::{Object, ObjectShape};
+
+    use super::Shape;
+
+    fn object(shape: ObjectShape, size: Vec2, rotation: f32) -> Object {
+        Object {
+            id: 1,
+            gid: None,
+            world_x: 0.0,
+            world_y: 0.0,
+            world_w: size.x,
+            world_h: size.y,
+            tile_x: 0,
+            tile_y: 0,
+            tile_w: 0,
+            tile_h: 0,
+            name: String::new(),
+            properties: HashMap::new(),
+            shape,
+            rotation,
+        }
+    }
+
+    #[test]
+    fn ellipse_edge() {
+        let ellipse = Shape::Ellipse {
+            center: Vec2::ZERO,
+            radii: vec2(20.0, 10.0),
+            rotation: 0.0,
+        };
+
+        assert!(ellipse.overlaps_rect(Rect::new(20.0, -5.0, 10.0, 10.0)));
+        assert!(!ellipse.overlaps_rect(Rect::new(20.5, -5.0, 10.0, 10.0)));
+        // inside the bounds but past the curve
+        assert!(!ellipse.overlaps_rect(Rect::new(15.0, 8.0, 10.0, 10.0)));
+        // covering it whole
+        assert!(ellipse.overlaps_rect(Rect::new(-30.0, -30.0, 60.0, 60.0)));
+    }
+
+    #[test]
+    fn rotated_ellipse() {
+        // a quarter turn makes it tall
+        let ellipse = Shape::from_object(&object(ObjectShape::Ellipse, vec2(40.0, 20.0), 90.0));
+        let Shape::Ellipse { center, .. } = ellipse else {
+            panic!("expected an ellipse, got {:?}", ellipse);
+        };
+
+        assert!(ellipse.overlaps_rect(Rect::new(center.x - 1.0, center.y + 18.0, 2.0, 2.0)));
+        assert!(!ellipse.overlaps_rect(Rect::new(center.x + 12.0, center.y - 1.0, 2.0, 2.0)));
+    }
+
+    #[test]
+    fn concave_polygon_notch() {
+        // a U, open at the bottom between x 10 and 20
+        let polygon = Shape::Polygon(vec![
+            vec2(0.0, 0.0),
+            vec2(30.0, 0.0),
+            vec2(30.0, 30.0),
+            vec2(20.0, 30.0),
+            vec2(20.0, 10.0),
+            vec2(10.0, 10.0),
+            vec2(10.0, 30.0),
+            vec2(0.0, 30.0),
+        ]);
+
+        assert!(!polygon.overlaps_rect(Rect::new(12.0, 15.0, 6.0, 10.0)));
+        assert!(polygon.overlaps_rect(Rect::new(2.0, 15.0, 5.0, 5.0)));
+        assert!(polygon.overlaps_rect(Rect::new(8.0, 15.0, 4.0, 4.0)));
+    }
+
+    #[test]
+    fn rotated_rect() {
+        // 20x10 turned a quarter clockwise around its top left corner ends
+        // up covering x -10..0, y 0..20
+        let rect = Shape::from_object(&object(ObjectShape::Rect, vec2(20.0, 10.0), 90.0));
+        assert!(matches!(rect, Shape::Polygon(_)));
+
+        assert!(rect.overlaps_rect(Rect::new(-8.0, 15.0, 2.0, 2.0)));
+        assert!(!rect.overlaps_rect(Rect::new(5.0, 2.0, 3.0, 3.0)));
+
+        let bounds = rect.bounds();
+        assert!((bounds.x + 10.0).abs() < 1e-4 && (bounds.h - 20.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn polyline_through_rect() {
+        let through = Shape::Polyline(vec![vec2(0.0, 5.0), vec2(30.0, 5.0)]);
+        assert!(through.overlaps_rect(Rect::new(10.0, 0.0, 10.0, 10.0)));
+
+        let beside = Shape::Polyline(vec![vec2(0.0, 15.0), vec2(30.0, 15.0)]);
+        assert!(!beside.overlaps_rect(Rect::new(10.0, 0.0, 10.0, 10.0)));
+
+        // its bounds overlap the rect, the line itself passes the corner
+        let diagonal = Shape::Polyline(vec![vec2(0.0, 20.0), vec2(20.0, 0.0)]);
+        assert!(!diagonal.overlaps_rect(Rect::new(15.0, 15.0, 5.0, 5.0)));
+        assert!(diagonal.overlaps_rect(Rect::new(5.0, 5.0, 5.0, 5.0)));
+    }
+}
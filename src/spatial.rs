@@ -0,0 +1,249 @@
+use std::collections::HashMap;
+
+use macroquad::math::{Rect, Vec2};
+use macroquad_tiled::Map;
+
+use crate::{
+    components::{collider::Collider, position::Position},
+    entity::entity::Entity,
+    parallel::WorldCell,
+    shape::Shape,
+};
+
+/// Four tiles, about the size of the biggest sprites.
+pub const CELL_SIZE: f32 = 64.0;
+
+type Cell = (i32, i32);
+
+/// An entity's boxes as of the last `spatial_index_system`.
+#[derive(Debug, Clone, Copy)]
+struct DynamicCollider {
+    entity: Entity,
+    collision: Rect,
+    hurtbox: Rect,
+}
+
+/// Uniform grid so collision checks only look at what's nearby. The map's
+/// `collisions` layer goes in once when the map loads, entities with a
+/// `Collider` are put back in every fixed step by `spatial_index_system`.
+pub struct SpatialIndex {
+    cell_size: f32,
+    statics: Vec<(Shape, Rect)>, // shape and its bounds
+    static_cells: HashMap<Cell, Vec<usize>>,
+    dynamics: Vec<DynamicCollider>,
+    dynamic_cells: HashMap<Cell, Vec<usize>>,
+}
+
+impl SpatialIndex {
+    pub fn new(cell_size: f32) -> Self {
+        Self {
+            cell_size,
+            statics: Vec::new(),
+            static_cells: HashMap::new(),
+            dynamics: Vec::new(),
+            dynamic_cells: HashMap::new(),
+        }
+    }
+
+    /// Index with every object of the map's `collisions` layer in it.
+    pub fn from_map(map: &Map, cell_size: f32) -> Self {
+        let mut index = Self::new(cell_size);
+        if let Some(layer) = map.layers.get("collisions") {
+            for object in &layer.objects {
+                index.insert_static(Shape::from_object(object));
+            }
+        }
+        index
+    }
+
+    pub fn insert_static(&mut self, shape: Shape) {
+        let id = self.statics.len();
+        let bounds = shape.bounds();
+        self.statics.push((shape, bounds));
+        for cell in cells(self.cell_size, bounds) {
+            self.static_cells.entry(cell).or_default().push(id);
+        }
+    }
+
+    /// Forgets every entity, the map stays. Cells keep their allocations
+    /// since the next step fills mostly the same ones.
+    pub fn clear_dynamic(&mut self) {
+        self.dynamics.clear();
+        for ids in self.dynamic_cells.values_mut() {
+            ids.clear();
+        }
+    }
+
+    pub fn insert_dynamic(&mut self, entity: Entity, collision: Rect, hurtbox: Rect) {
+        let id = self.dynamics.len();
+        self.dynamics.push(DynamicCollider {
+            entity,
+            collision,
+            hurtbox,
+        });
+        for cell in cells(self.cell_size, collision.combine_with(hurtbox)) {
+            self.dynamic_cells.entry(cell).or_default().push(id);
+        }
+    }
+
+    /// Whether `rect` touches anything in the map's collision layer, by its
+    /// true shape.
+    pub fn collides_with_map(&self, rect: Rect) -> bool {
+        cells(self.cell_size, rect)
+            .filter_map(|cell| self.static_cells.get(&cell))
+            .flatten()
+            .any(|&id| {
+                let (shape, bounds) = &self.statics[id];
+                bounds.overlaps(&rect) && shape.overlaps_rect(rect)
+            })
+    }
+
+    /// Entities whose collision box overlaps `rect`.
+    pub fn colliders_overlapping(&self, rect: Rect) -> Vec<Entity> {
+        self.dynamic_overlapping(rect, |collider| collider.collision)
+    }
+
+    /// Entities whose hurtbox overlaps `rect`.
+    pub fn hurtboxes_overlapping(&self, rect: Rect) -> Vec<Entity> {
+        self.dynamic_overlapping(rect, |collider| collider.hurtbox)
+    }
+
+    /// In insertion order, so results don't depend on hash map order.
+    fn dynamic_overlapping(
+        &self,
+        rect: Rect,
+        shape: impl Fn(&DynamicCollider) -> Rect,
+    ) -> Vec<Entity> {
+        let mut ids: Vec<usize> = cells(self.cell_size, rect)
+            .filter_map(|cell| self.dynamic_cells.get(&cell))
+            .flatten()
+            .copied()
+            .collect();
+        // big entries sit in several cells
+        ids.sort_unstable();
+        ids.dedup();
+
+        ids.into_iter()
+            .map(|id| &self.dynamics[id])
+            .filter(|collider| shape(collider).overlaps(&rect))
+            .map(|collider| collider.entity)
+            .collect()
+    }
+}
+
+/// Every cell `rect` touches, edges included like `Rect::overlaps`.
+fn cells(cell_size: f32, rect: Rect) -> impl Iterator<Item = Cell> {
+    let min_x = (rect.left() / cell_size).floor() as i32;
+    let min_y = (rect.top() / cell_size).floor() as i32;
+    let max_x = (rect.right() / cell_size).floor() as i32;
+    let max_y = (rect.bottom() / cell_size).floor() as i32;
+
+    (min_x..=max_x).flat_map(move |x| (min_y..=max_y).map(move |y| (x, y)))
+}
+
+/// Puts every entity with a `Collider` back in the index at its current
+/// position. Runs at the start of each fixed step, before combat.
+pub fn spatial_index_system(world: &mut WorldCell) {
+    world.resource_scope::<SpatialIndex, _>(|world, index| {
+        index.clear_dynamic();
+        for (entity, position, collider) in world.query::<(Entity, &Position, &Collider)>() {
+            let position = Vec2::new(position.x, position.y);
+            index.insert_dynamic(
+                entity,
+                collider.collision_box(position),
+                collider.hurtbox(position),
+            );
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use macroquad::math::{vec2, Rect};
+
+    use super::{cells, spatial_index_system, SpatialIndex, CELL_SIZE};
+    use crate::{
+        components::{collider::Collider, position::Position},
+        schedule::{Schedule, Stage},
+        world::World,
+    };
+
+    fn all_cells(rect: Rect) -> Vec<(i32, i32)> {
+        cells(CELL_SIZE, rect).collect()
+    }
+
+    #[test]
+    fn rect_inside_one_cell() {
+        assert_eq!(all_cells(Rect::new(10.0, 10.0, 20.0, 20.0)), vec![(0, 0)]);
+    }
+
+    #[test]
+    fn rect_straddling_cells() {
+        assert_eq!(
+            all_cells(Rect::new(60.0, 10.0, 10.0, 60.0)),
+            vec![(0, 0), (0, 1), (1, 0), (1, 1)]
+        );
+        // ending right on a boundary touches the next cell, like `overlaps`
+        assert_eq!(
+            all_cells(Rect::new(0.0, 0.0, 64.0, 10.0)),
+            vec![(0, 0), (1, 0)]
+        );
+    }
+
+    #[test]
+    fn rect_in_negative_coordinates() {
+        assert_eq!(all_cells(Rect::new(-10.0, -70.0, 5.0, 5.0)), vec![(-1, -2)]);
+        assert_eq!(
+            all_cells(Rect::new(-10.0, -10.0, 20.0, 5.0)),
+            vec![(-1, -1), (0, -1)]
+        );
+    }
+
+    #[test]
+    fn rect_outside_the_map_still_finds_entities() {
+        let mut index = SpatialIndex::new(CELL_SIZE);
+        let mut world = World::new();
+        let far = world.spawn_entity().id();
+        let rect = Rect::new(-1000.0, 5000.0, 10.0, 10.0);
+        index.insert_dynamic(far, rect, rect);
+
+        assert_eq!(index.colliders_overlapping(rect), vec![far]);
+        assert!(index
+            .colliders_overlapping(Rect::new(0.0, 0.0, 10.0, 10.0))
+            .is_empty());
+    }
+
+    #[test]
+    fn moved_entities_are_reindexed() {
+        let mut world = World::new();
+        world.insert_resource(SpatialIndex::new(CELL_SIZE));
+        let entity = world
+            .spawn_entity()
+            .with(Position { x: 10.0, y: 10.0 })
+            .with(Collider {
+                collision_offset: vec2(0.0, 0.0),
+                collision_size: vec2(10.0, 10.0),
+                sprite_padding: vec2(0.0, 0.0),
+                visible_size: vec2(10.0, 10.0),
+            })
+            .id();
+
+        let mut schedule = Schedule::new();
+        schedule
+            .add_parallel_system(Stage::Update, spatial_index_system)
+            .access::<(&Position, &Collider)>()
+            .resource_mut::<SpatialIndex>();
+        schedule.run(&mut world);
+
+        let start = Rect::new(12.0, 12.0, 2.0, 2.0);
+        let end = Rect::new(202.0, 12.0, 2.0, 2.0);
+        assert_eq!(world.hurtboxes_overlapping(start), vec![entity]);
+        assert!(world.hurtboxes_overlapping(end).is_empty());
+
+        world.get_component_mut::<Position>(entity).unwrap().x = 200.0;
+        schedule.run(&mut world);
+
+        assert!(world.hurtboxes_overlapping(start).is_empty());
+        assert_eq!(world.hurtboxes_overlapping(end), vec![entity]);
+    }
+}
@@ -0,0 +1,512 @@
+use std::cell::UnsafeCell;
+
+/// How a component type is laid out in the `World`.
+///
+/// `Dense` keeps one slot per entity, which is fastest for components most
+/// entities have (Position, Sprite). `SparseSet` only stores the entities that
+/// actually have the component, so rare ones (Player, Enemy) don't pay for
+/// every tile object, and queries over them only visit those entities.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum StorageType {
+    #[default]
+    Dense,
+    SparseSet,
+}
+
+/// World change ticks at which a component was inserted and last borrowed
+/// mutably. Used by the `Added` and `Changed` query filters.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ComponentTicks {
+    pub added: u32,
+    pub changed: u32,
+}
+
+impl ComponentTicks {
+    fn new(tick: u32) -> Self {
+        Self {
+            added: tick,
+            changed: tick,
+        }
+    }
+
+    /// Whether the component was inserted after `last_run`, `this_run` being
+    /// the current tick.
+    pub fn is_added(&self, last_run: u32, this_run: u32) -> bool {
+        is_newer(self.added, last_run, this_run)
+    }
+
+    /// Whether the component was borrowed mutably after `last_run`.
+    pub fn is_changed(&self, last_run: u32, this_run: u32) -> bool {
+        is_newer(self.changed, last_run, this_run)
+    }
+
+    /// Clamps ticks older than `MAX_CHANGE_AGE`, see `check_tick`.
+    pub fn check_ticks(&mut self, change_tick: u32) {
+        check_tick(&mut self.added, change_tick);
+        check_tick(&mut self.changed, change_tick);
+    }
+}
+
+/// How many ticks can pass between two `World::check_change_ticks`.
+pub const CHECK_TICK_THRESHOLD: u32 = 518_400_000;
+
+/// Oldest a tick gets once clamped. Ticks only age by `CHECK_TICK_THRESHOLD`
+/// more before the next check, which still leaves them short of wrapping.
+pub const MAX_CHANGE_AGE: u32 = u32::MAX - (2 * CHECK_TICK_THRESHOLD - 1);
+
+/// Compares how far back from `this_run` both ticks are, so it keeps working
+/// after the tick wraps around. That only holds for ticks less than
+/// `u32::MAX` ticks old, which `check_tick` makes sure of.
+fn is_newer(tick: u32, last_run: u32, this_run: u32) -> bool {
+    this_run.wrapping_sub(tick) < this_run.wrapping_sub(last_run)
+}
+
+/// Pulls a tick older than `MAX_CHANGE_AGE` up to that age, so it can't wrap
+/// around and look new again. Whatever it gets compared against was clamped
+/// the same way, so old stays old.
+pub(crate) fn check_tick(tick: &mut u32, change_tick: u32) {
+    if change_tick.wrapping_sub(*tick) > MAX_CHANGE_AGE {
+        *tick = change_tick.wrapping_sub(MAX_CHANGE_AGE);
+    }
+}
+
+pub struct DenseVec<T> {
+    data: Vec<UnsafeCell<Option<T>>>,
+    ticks: Vec<UnsafeCell<ComponentTicks>>,
+}
+
+pub struct SparseSet<T> {
+    sparse: Vec<Option<usize>>, // entity index -> position in dense
+    dense: Vec<UnsafeCell<T>>,
+    ticks: Vec<UnsafeCell<ComponentTicks>>, // same order as dense
+    entities: Vec<usize>,                   // position in dense -> entity index
+}
+
+/// Components and their ticks sit in `UnsafeCell`s so a query can write
+/// them through a `ColumnPtr` taken from a shared borrow, which is how
+/// parallel systems get at them.
+pub enum ComponentStorage<T> {
+    Dense(DenseVec<T>),
+    SparseSet(SparseSet<T>),
+}
+
+/// Raw view of a storage, taken once per query so fetching a row is just
+/// pointer arithmetic. Whoever takes one has to make sure nothing else
+/// touches the components it writes meanwhile.
+pub enum ColumnPtr<T> {
+    Dense {
+        data: *mut Option<T>,
+        ticks: *mut ComponentTicks,
+        len: usize,
+        change_tick: u32,
+    },
+    SparseSet {
+        sparse: *const Option<usize>,
+        len: usize,
+        dense: *mut T,
+        ticks: *mut ComponentTicks,
+        entities: *const usize,
+        count: usize,
+        change_tick: u32,
+    },
+}
+
+impl<T> Clone for ColumnPtr<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for ColumnPtr<T> {}
+
+impl<T> ColumnPtr<T> {
+    /// Position of the entity's component in the data and ticks arrays.
+    ///
+    /// # Safety
+    /// The storage this was taken from must still be alive and unchanged in
+    /// size.
+    unsafe fn position(self, index: usize) -> Option<usize> {
+        unsafe {
+            match self {
+                ColumnPtr::Dense { data, len, .. } => {
+                    (index < len && (*data.add(index)).is_some()).then_some(index)
+                }
+                ColumnPtr::SparseSet { sparse, len, .. } => {
+                    if index >= len {
+                        return None;
+                    }
+                    *sparse.add(index)
+                }
+            }
+        }
+    }
+
+    /// # Safety
+    /// Same as `position`.
+    pub unsafe fn contains(self, index: usize) -> bool {
+        unsafe { self.position(index).is_some() }
+    }
+
+    /// # Safety
+    /// Same as `position`, and nothing may borrow the same component mutably
+    /// meanwhile.
+    pub unsafe fn get<'a>(self, index: usize) -> Option<&'a T> {
+        unsafe {
+            let position = self.position(index)?;
+            match self {
+                ColumnPtr::Dense { data, .. } => (*data.add(position)).as_ref(),
+                ColumnPtr::SparseSet { dense, .. } => Some(&*dense.add(position)),
+            }
+        }
+    }
+
+    /// Also marks the component as changed.
+    ///
+    /// # Safety
+    /// Same as `get`, and nothing else may borrow the component at all.
+    pub unsafe fn get_mut<'a>(self, index: usize) -> Option<&'a mut T> {
+        unsafe {
+            let position = self.position(index)?;
+            match self {
+                ColumnPtr::Dense {
+                    data,
+                    ticks,
+                    change_tick,
+                    ..
+                } => {
+                    (*ticks.add(position)).changed = change_tick;
+                    (*data.add(position)).as_mut()
+                }
+                ColumnPtr::SparseSet {
+                    dense,
+                    ticks,
+                    change_tick,
+                    ..
+                } => {
+                    (*ticks.add(position)).changed = change_tick;
+                    Some(&mut *dense.add(position))
+                }
+            }
+        }
+    }
+
+    /// # Safety
+    /// Same as `position`, and nothing may be fetching the component mutably
+    /// meanwhile.
+    pub unsafe fn ticks(self, index: usize) -> Option<ComponentTicks> {
+        unsafe {
+            let position = self.position(index)?;
+            match self {
+                ColumnPtr::Dense { ticks, .. } | ColumnPtr::SparseSet { ticks, .. } => {
+                    Some(*ticks.add(position))
+                }
+            }
+        }
+    }
+
+    pub fn change_tick(self) -> u32 {
+        match self {
+            ColumnPtr::Dense { change_tick, .. } | ColumnPtr::SparseSet { change_tick, .. } => {
+                change_tick
+            }
+        }
+    }
+
+    /// Entity indices that have the component, if the storage knows them
+    /// without scanning every slot.
+    ///
+    /// # Safety
+    /// Same as `position`.
+    pub unsafe fn entities<'a>(self) -> Option<&'a [usize]> {
+        match self {
+            ColumnPtr::Dense { .. } => None,
+            ColumnPtr::SparseSet {
+                entities, count, ..
+            } => Some(unsafe { std::slice::from_raw_parts(entities, count) }),
+        }
+    }
+}
+
+impl<T> ComponentStorage<T> {
+    pub fn new(storage_type: StorageType, len: usize) -> Self {
+        match storage_type {
+            StorageType::Dense => {
+                let mut vec = DenseVec {
+                    data: Vec::with_capacity(len),
+                    ticks: Vec::with_capacity(len),
+                };
+                vec.data.resize_with(len, || UnsafeCell::new(None));
+                vec.ticks.resize_with(len, UnsafeCell::default);
+                ComponentStorage::Dense(vec)
+            }
+            StorageType::SparseSet => ComponentStorage::SparseSet(SparseSet {
+                sparse: vec![None; len],
+                dense: Vec::new(),
+                ticks: Vec::new(),
+                entities: Vec::new(),
+            }),
+        }
+    }
+
+    pub fn storage_type(&self) -> StorageType {
+        match self {
+            ComponentStorage::Dense(_) => StorageType::Dense,
+            ComponentStorage::SparseSet(_) => StorageType::SparseSet,
+        }
+    }
+
+    /// Number of entity slots the storage covers.
+    pub fn len(&self) -> usize {
+        match self {
+            ComponentStorage::Dense(vec) => vec.data.len(),
+            ComponentStorage::SparseSet(set) => set.sparse.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Grows the storage by one entity slot.
+    pub fn push_none(&mut self) {
+        match self {
+            ComponentStorage::Dense(vec) => {
+                vec.data.push(UnsafeCell::new(None));
+                vec.ticks.push(UnsafeCell::default());
+            }
+            ComponentStorage::SparseSet(set) => set.sparse.push(None),
+        }
+    }
+
+    pub fn get(&self, index: usize) -> Option<&T> {
+        match self {
+            ComponentStorage::Dense(vec) => shared(vec.data.get(index)?).as_ref(),
+            ComponentStorage::SparseSet(set) => {
+                let position = (*set.sparse.get(index)?)?;
+                Some(shared(&set.dense[position]))
+            }
+        }
+    }
+
+    /// Mutable access, marking the component as changed at `change_tick`.
+    pub fn get_mut(&mut self, index: usize, change_tick: u32) -> Option<&mut T> {
+        match self {
+            ComponentStorage::Dense(vec) => {
+                let component = vec.data.get_mut(index)?.get_mut().as_mut()?;
+                vec.ticks[index].get_mut().changed = change_tick;
+                Some(component)
+            }
+            ComponentStorage::SparseSet(set) => {
+                let position = (*set.sparse.get(index)?)?;
+                set.ticks[position].get_mut().changed = change_tick;
+                Some(set.dense[position].get_mut())
+            }
+        }
+    }
+
+    pub fn ticks(&self, index: usize) -> Option<ComponentTicks> {
+        match self {
+            ComponentStorage::Dense(vec) => {
+                shared(vec.data.get(index)?).as_ref()?;
+                Some(*shared(&vec.ticks[index]))
+            }
+            ComponentStorage::SparseSet(set) => {
+                let position = (*set.sparse.get(index)?)?;
+                Some(*shared(&set.ticks[position]))
+            }
+        }
+    }
+
+    /// Stores the component for the entity, returning the one it replaced.
+    /// A replaced component counts as changed, a new one as added.
+    pub fn insert(&mut self, index: usize, component: T, change_tick: u32) -> Option<T> {
+        let replaced = self.remove_with_ticks(index);
+        let ticks = match replaced {
+            Some((_, ticks)) => ComponentTicks {
+                added: ticks.added,
+                changed: change_tick,
+            },
+            None => ComponentTicks::new(change_tick),
+        };
+        self.insert_with_ticks(index, component, ticks);
+        replaced.map(|(component, _)| component)
+    }
+
+    pub fn remove(&mut self, index: usize) -> Option<T> {
+        self.remove_with_ticks(index)
+            .map(|(component, _)| component)
+    }
+
+    fn insert_with_ticks(&mut self, index: usize, component: T, ticks: ComponentTicks) {
+        match self {
+            ComponentStorage::Dense(vec) => {
+                *vec.data[index].get_mut() = Some(component);
+                *vec.ticks[index].get_mut() = ticks;
+            }
+            ComponentStorage::SparseSet(set) => {
+                set.sparse[index] = Some(set.dense.len());
+                set.dense.push(UnsafeCell::new(component));
+                set.ticks.push(UnsafeCell::new(ticks));
+                set.entities.push(index);
+            }
+        }
+    }
+
+    fn remove_with_ticks(&mut self, index: usize) -> Option<(T, ComponentTicks)> {
+        match self {
+            ComponentStorage::Dense(vec) => {
+                let component = vec.data.get_mut(index)?.get_mut().take()?;
+                Some((component, *vec.ticks[index].get_mut()))
+            }
+            ComponentStorage::SparseSet(set) => {
+                let position = set.sparse.get_mut(index)?.take()?;
+
+                // swap the last component into the hole and repoint its entity
+                let component = set.dense.swap_remove(position).into_inner();
+                let ticks = set.ticks.swap_remove(position).into_inner();
+                set.entities.swap_remove(position);
+                if let Some(&moved) = set.entities.get(position) {
+                    set.sparse[moved] = Some(position);
+                }
+                Some((component, ticks))
+            }
+        }
+    }
+
+    /// Number of entities that actually have the component.
+    pub fn count(&self) -> usize {
+        match self {
+            ComponentStorage::Dense(vec) => vec
+                .data
+                .iter()
+                .filter(|slot| shared(slot).is_some())
+                .count(),
+            ComponentStorage::SparseSet(set) => set.dense.len(),
+        }
+    }
+
+    /// See `World::check_change_ticks`.
+    pub fn check_change_ticks(&mut self, change_tick: u32) {
+        let ticks = match self {
+            ComponentStorage::Dense(vec) => &mut vec.ticks,
+            ComponentStorage::SparseSet(set) => &mut set.ticks,
+        };
+        for ticks in ticks {
+            ticks.get_mut().check_ticks(change_tick);
+        }
+    }
+
+    /// Moves every component into a storage of the given type.
+    pub fn convert(&mut self, storage_type: StorageType) {
+        if self.storage_type() == storage_type {
+            return;
+        }
+
+        let len = self.len();
+        let mut converted = ComponentStorage::new(storage_type, len);
+        for index in 0..len {
+            if let Some((component, ticks)) = self.remove_with_ticks(index) {
+                converted.insert_with_ticks(index, component, ticks);
+            }
+        }
+        *self = converted;
+    }
+
+    pub fn as_ptr(&self, change_tick: u32) -> ColumnPtr<T> {
+        match self {
+            ComponentStorage::Dense(vec) => ColumnPtr::Dense {
+                data: UnsafeCell::raw_get(vec.data.as_ptr()),
+                ticks: UnsafeCell::raw_get(vec.ticks.as_ptr()),
+                len: vec.data.len(),
+                change_tick,
+            },
+            ComponentStorage::SparseSet(set) => ColumnPtr::SparseSet {
+                sparse: set.sparse.as_ptr(),
+                len: set.sparse.len(),
+                dense: UnsafeCell::raw_get(set.dense.as_ptr()),
+                ticks: UnsafeCell::raw_get(set.ticks.as_ptr()),
+                entities: set.entities.as_ptr(),
+                count: set.entities.len(),
+                change_tick,
+            },
+        }
+    }
+}
+
+/// Reads a cell through a shared borrow of its storage.
+fn shared<T>(cell: &UnsafeCell<T>) -> &T {
+    // SAFETY: writes to a shared storage only go through a `ColumnPtr`, and
+    // whoever took that one makes sure nothing reads the same components
+    // meanwhile (`World` borrows, or a parallel system's declared access).
+    unsafe { &*cell.get() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ComponentStorage, StorageType, CHECK_TICK_THRESHOLD, MAX_CHANGE_AGE};
+
+    #[test]
+    fn sparse_set_remove_keeps_the_others() {
+        let mut storage = ComponentStorage::new(StorageType::SparseSet, 3);
+        for index in 0..3 {
+            storage.insert(index, index * 10, 1);
+        }
+
+        // the last component is swapped into the hole
+        assert_eq!(storage.remove(0), Some(0));
+        assert_eq!(storage.remove(0), None);
+        assert_eq!(storage.get(0), None);
+        assert_eq!(storage.get(1), Some(&10));
+        assert_eq!(storage.get(2), Some(&20));
+        assert_eq!(storage.count(), 2);
+
+        assert_eq!(storage.remove(2), Some(20));
+        assert_eq!(storage.get(1), Some(&10));
+        assert_eq!(storage.count(), 1);
+    }
+
+    #[test]
+    fn convert_keeps_components_and_ticks() {
+        let mut storage = ComponentStorage::new(StorageType::Dense, 4);
+        storage.insert(1, "a", 1);
+        storage.insert(3, "b", 2);
+        storage.get_mut(3, 5);
+
+        for storage_type in [StorageType::SparseSet, StorageType::Dense] {
+            storage.convert(storage_type);
+            assert_eq!(storage.storage_type(), storage_type);
+            assert_eq!(storage.len(), 4);
+            assert_eq!(storage.count(), 2);
+            assert_eq!(storage.get(0), None);
+            assert_eq!(storage.get(1), Some(&"a"));
+            assert_eq!(storage.get(3), Some(&"b"));
+
+            let ticks = storage.ticks(3).unwrap();
+            assert_eq!((ticks.added, ticks.changed), (2, 5));
+        }
+    }
+
+    #[test]
+    fn old_ticks_are_clamped_before_they_wrap() {
+        let mut storage = ComponentStorage::new(StorageType::Dense, 1);
+        storage.insert(0, "old", 0);
+
+        // checked every threshold while the tick goes all the way around
+        let mut tick = 0u32;
+        for _ in 0..9 {
+            tick = tick.wrapping_add(CHECK_TICK_THRESHOLD);
+            storage.check_change_ticks(tick);
+        }
+        assert!(tick < CHECK_TICK_THRESHOLD, "the tick wrapped");
+
+        // a system that last ran as long ago as anything can be. Left alone,
+        // tick 0 would look newer than that.
+        let last_run = tick.wrapping_sub(MAX_CHANGE_AGE);
+        let ticks = storage.ticks(0).unwrap();
+        assert!(!ticks.is_added(last_run, tick));
+        assert!(!ticks.is_changed(last_run, tick));
+
+        storage.get_mut(0, tick);
+        assert!(storage.ticks(0).unwrap().is_changed(last_run, tick));
+    }
+}
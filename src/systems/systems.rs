@@ -1,122 +1,179 @@
 use macroquad::{
-    camera::set_camera,
-    color::{RED, WHITE},
-    input::{is_key_down, KeyCode},
+    camera::Camera2D,
+    color::BLANK,
+    input::{is_key_down, is_key_pressed, KeyCode},
     math::{Rect, Vec2},
-    shapes::draw_rectangle,
-    texture::{draw_texture_ex, DrawTextureParams},
     time::get_frame_time,
 };
-use macroquad_tiled::Map;
 
 use crate::{
+    commands::Commands,
     components::{
         collider::Collider,
+        contact::Contact,
         direction::Direction,
         enemy::{AIType, Enemy},
-        player::{self, Player},
-        position::Position,
-        sprite::Sprite,
+        hierarchy::{Children, Parent},
+        player::Player,
+        position::{LocalPosition, Position, PreviousPosition},
+        rng::RngStream,
+        sprite::{advance_animation, Sprite},
         velocity::Velocity,
     },
-    resources::Resources,
-    world::{self, World, WORLD_HEIGHT, WORLD_WIDTH},
+    entity::entity::Entity,
+    events::{EnemyKilled, PlayerHit},
+    filter::{With, Without},
+    parallel::WorldCell,
+    render::{DrawCommand, DrawQueue},
+    resources::{FixedTime, GameRng, InputState, Time},
+    save::SaveSlot,
+    spatial::SpatialIndex,
+    world::{World, WORLD_HEIGHT, WORLD_WIDTH},
 };
 
 pub fn render_systems(world: &mut World) {
-    for (sprite, position) in world.query::<(&Sprite, &Position)>() {
+    let alpha = fixed_alpha(world);
+    let mut draws = Vec::new();
+    for (sprite, position, previous) in
+        world.query::<(&Sprite, &Position, Option<&PreviousPosition>)>()
+    {
         let source = if let Some(anim) = &sprite.animation {
             Some(anim.frame().source_rect) // animated
         } else {
             sprite.source_rect // static
         };
 
-        draw_texture_ex(
-            &sprite.texture,
-            position.x,
-            position.y,
-            WHITE,
-            DrawTextureParams {
-                source,
-                dest_size: sprite.dest_size,
-                flip_x: sprite.flipped,
-                ..Default::default()
-            },
-        );
+        draws.push(DrawCommand::Sprite {
+            texture: sprite.texture.clone(),
+            position: position.interpolated(previous, alpha),
+            source,
+            dest_size: sprite.dest_size,
+            flip_x: sprite.flipped,
+        });
+    }
+
+    let queue = world.resource_mut::<DrawQueue>();
+    for draw in draws {
+        queue.push(draw);
+    }
+}
+
+pub fn clear_screen_system(world: &mut World) {
+    world
+        .resource_mut::<DrawQueue>()
+        .push(DrawCommand::Clear(BLANK));
+}
+
+/// Tile layers drawn below the sprites.
+pub fn tilemap_render_system(world: &mut World) {
+    let queue = world.resource_mut::<DrawQueue>();
+    for layer in ["background", "decorations", "decorations_2"] {
+        queue.push(DrawCommand::TileLayer(layer));
     }
 }
 
-pub fn tilemap_render_system(tiled_map: &Map, world: &mut World) {
-    tiled_map.draw_tiles(
-        "background",
-        Rect::new(0.0, 0.0, WORLD_WIDTH, WORLD_HEIGHT),
-        None,
-    );
-    tiled_map.draw_tiles(
-        "decorations",
-        Rect::new(0.0, 0.0, WORLD_WIDTH, WORLD_HEIGHT),
-        None,
-    );
-    tiled_map.draw_tiles(
-        "decorations_2",
-        Rect::new(0.0, 0.0, WORLD_WIDTH, WORLD_HEIGHT),
-        None,
-    );
-
-    // render order here
-    render_systems(world);
-
-    tiled_map.draw_tiles(
-        "foreground",
-        Rect::new(0.0, 0.0, WORLD_WIDTH, WORLD_HEIGHT),
-        None,
-    );
+/// Tile layers drawn above the sprites.
+pub fn foreground_render_system(world: &mut World) {
+    world
+        .resource_mut::<DrawQueue>()
+        .push(DrawCommand::TileLayer("foreground"));
+}
+
+/// The only place frame time is read from macroquad, everything else uses `Time`.
+pub fn time_system(world: &mut World) {
+    world.resource_mut::<Time>().advance(get_frame_time());
+}
+
+/// The only place the keyboard is read, everything else uses `InputState`.
+pub fn keyboard_system(world: &mut World) {
+    *world.resource_mut::<InputState>() = InputState {
+        up: is_key_down(KeyCode::Up),
+        down: is_key_down(KeyCode::Down),
+        left: is_key_down(KeyCode::Left),
+        right: is_key_down(KeyCode::Right),
+        attack: is_key_down(KeyCode::Z),
+        save: is_key_pressed(KeyCode::F5),
+        load: is_key_pressed(KeyCode::F9),
+        inspector: is_key_pressed(KeyCode::F1),
+    };
+}
+
+/// F5 saves, F9 loads, to and from the world's `SaveSlot`. Loading replaces
+/// every entity, so this has to run on its own before anything queries the
+/// world.
+pub fn save_load_system(world: &mut World) {
+    let input = *world.resource::<InputState>();
+    if !input.save && !input.load {
+        return;
+    }
+
+    world.resource_scope::<SaveSlot, _>(|world, slot| {
+        if input.save {
+            match slot.save(world) {
+                Ok(()) => println!("saved to {}", slot),
+                Err(e) => println!("save failed: {}", e),
+            }
+        }
+
+        if input.load {
+            match slot.load(world) {
+                Ok(()) => println!("loaded {}", slot),
+                Err(e) => println!("load failed: {}", e),
+            }
+        }
+    });
 }
 
-fn player_animation_system(world: &mut World) {
+fn player_animation_system(world: &mut WorldCell) {
+    let dt = world.resource::<Time>().delta;
     for (sprite, velocity, player) in world.query::<(&mut Sprite, &Velocity, &Player)>() {
-        player.handle_player_animation(velocity, sprite, player);
+        player.handle_player_animation(velocity, sprite, player, dt);
     }
 }
 
-fn enemy_animation_system(world: &mut World) {
+fn enemy_animation_system(world: &mut WorldCell) {
+    let dt = world.resource::<Time>().delta;
     for (sprite, velocity, enemy) in world.query::<(&mut Sprite, &Velocity, &mut Enemy)>() {
-        enemy.handle_enemy_animation(velocity, sprite);
+        enemy.handle_enemy_animation(velocity, sprite, dt);
     }
 }
 
-pub fn animation_systems(world: &mut World) {
+pub fn animation_systems(world: &mut WorldCell) {
     // update moving animation
     player_animation_system(world);
     enemy_animation_system(world);
 
     // update unmoving entities
+    let dt = world.resource::<Time>().delta;
     for sprite in world.query::<&mut Sprite>() {
         if let Some(ref mut anim) = sprite.animation {
-            anim.update();
+            advance_animation(anim, sprite.sheet.as_ref(), &mut sprite.frame_timer, dt);
         }
     }
 }
 
-pub fn input_systems(world: &mut World) {
+pub fn input_systems(world: &mut WorldCell) {
+    let input = *world.resource::<InputState>();
+    let dt = world.resource::<Time>().delta;
+
     for (velocity, player) in world.query::<(&mut Velocity, &mut Player)>() {
         velocity.x = 0.;
         velocity.y = 0.;
 
         // movement related keypresses
-        if is_key_down(KeyCode::Up) {
+        if input.up {
             velocity.y = -player.walk_speed;
             player.last_direction = Direction::Up;
         }
-        if is_key_down(KeyCode::Down) {
+        if input.down {
             velocity.y = player.walk_speed;
             player.last_direction = Direction::Down;
         }
-        if is_key_down(KeyCode::Left) {
+        if input.left {
             velocity.x = -player.walk_speed;
             player.last_direction = Direction::Left;
         }
-        if is_key_down(KeyCode::Right) {
+        if input.right {
             velocity.x = player.walk_speed;
             player.last_direction = Direction::Right;
         }
@@ -129,14 +186,14 @@ pub fn input_systems(world: &mut World) {
         }
 
         // attack related kepresses
-        if is_key_down(KeyCode::Z) {
+        if input.attack {
             player.attacking = true;
             player.attack_timer = 0.3;
         }
 
         // countdown attack_timer
         if player.attack_timer >= 0.0 {
-            player.attack_timer -= get_frame_time();
+            player.attack_timer -= dt;
             if player.attack_timer <= 0.0 {
                 player.attacking = false;
             }
@@ -144,13 +201,15 @@ pub fn input_systems(world: &mut World) {
     }
 }
 
-pub fn enemy_aggro_system(world: &mut World) {
-    let player_pos =
-        if let Some((position, _)) = world.query::<(&Position, &Player)>().iter().next() {
-            Vec2::new(position.x, position.y)
-        } else {
-            return;
-        };
+pub fn enemy_aggro_system(world: &mut WorldCell) {
+    let player_pos = if let Some(position) = world
+        .query_filtered::<&Position, With<Player>>()
+        .get_single()
+    {
+        Vec2::new(position.x, position.y)
+    } else {
+        return;
+    };
 
     for (enemy_pos, enemy) in world.query::<(&Position, &mut Enemy)>() {
         let enemy_pos_vec = Vec2::new(enemy_pos.x, enemy_pos.y);
@@ -166,69 +225,89 @@ pub fn enemy_aggro_system(world: &mut World) {
     }
 }
 
-pub fn enemy_movement_systems(world: &mut World) {
-    let player_pos =
-        if let Some((position, _)) = world.query::<(&Position, &Player)>().iter().next() {
-            Vec2::new(position.x + 23., position.y + 29.)
-        } else {
-            return;
-        };
-
-    let dt = get_frame_time();
-
-    for (enemy_pos, velocity, enemy) in world.query::<(&Position, &mut Velocity, &mut Enemy)>() {
-        // + 12 to account for sprite padding
-        let enemy_position = Vec2::new(enemy_pos.x + 12., enemy_pos.y + 12.);
-        let direction = (player_pos - enemy_position).normalize();
-
-        match enemy.ai_type {
-            AIType::Wander => {
-                enemy.attacking = false;
-                enemy.movement_timer += dt;
-
-                if enemy.movement_timer >= enemy.change_direction_interval {
-                    enemy.movement_timer = 0.;
-                    enemy.change_direction(velocity, enemy.walk_speed);
+pub fn enemy_movement_systems(world: &mut WorldCell) {
+    let player_pos = if let Some(position) = world
+        .query_filtered::<&Position, With<Player>>()
+        .get_single()
+    {
+        Vec2::new(position.x + 23., position.y + 29.)
+    } else {
+        return;
+    };
+
+    let dt = world.resource::<Time>().delta;
+
+    world.resource_scope::<GameRng, _>(|world, rng| {
+        for (enemy_pos, velocity, enemy, stream, contact) in world.query::<(
+            &Position,
+            &mut Velocity,
+            &mut Enemy,
+            Option<&mut RngStream>,
+            Option<&Contact>,
+        )>() {
+            // + 12 to account for sprite padding
+            let enemy_position = Vec2::new(enemy_pos.x + 12., enemy_pos.y + 12.);
+            let direction = (player_pos - enemy_position).normalize();
+
+            match enemy.ai_type {
+                AIType::Wander => {
+                    enemy.attacking = false;
+                    enemy.movement_timer += dt;
+                    let bumped = contact.is_some_and(Contact::any);
+
+                    if bumped || enemy.movement_timer >= enemy.change_direction_interval {
+                        enemy.movement_timer = 0.;
+                        match stream {
+                            Some(stream) => {
+                                enemy.change_direction(velocity, enemy.walk_speed, stream)
+                            }
+                            None => enemy.change_direction(velocity, enemy.walk_speed, rng),
+                        }
+                    }
                 }
-            }
-            AIType::ChasePlayer => {
-                enemy.attacking = false;
-                velocity.x = direction.x * enemy.chase_speed;
-                velocity.y = direction.y * enemy.chase_speed;
-            }
-            AIType::Attack => {
-                enemy.attack_timer += dt;
-                if enemy.attack_timer >= enemy.attack_interval {
-                    enemy.attack_timer = 0.;
-                    enemy.attacking = true;
-                    enemy.attack_animation_timer = 0.;
+                AIType::ChasePlayer => {
+                    enemy.attacking = false;
+                    velocity.x = direction.x * enemy.chase_speed;
+                    velocity.y = direction.y * enemy.chase_speed;
                 }
+                AIType::Attack => {
+                    enemy.attack_timer += dt;
+                    if enemy.attack_timer >= enemy.attack_interval {
+                        enemy.attack_timer = 0.;
+                        enemy.attacking = true;
+                        enemy.attack_animation_timer = 0.;
+                    }
 
-                if enemy.attacking {
-                    enemy.attack_animation_timer += dt;
+                    if enemy.attacking {
+                        enemy.attack_animation_timer += dt;
 
-                    // Keep moving during the entire attack animation
-                    velocity.x = direction.x * enemy.attack_speed;
-                    velocity.y = direction.y * enemy.attack_speed;
+                        // Keep moving during the entire attack animation
+                        velocity.x = direction.x * enemy.attack_speed;
+                        velocity.y = direction.y * enemy.attack_speed;
 
-                    if enemy.attack_animation_timer >= enemy.attack_animation_duration {
-                        enemy.attacking = false;
+                        if enemy.attack_animation_timer >= enemy.attack_animation_duration {
+                            enemy.attacking = false;
+                        }
+                    } else {
+                        velocity.x = 0.;
+                        velocity.y = 0.;
                     }
-                } else {
+                }
+                AIType::Dead => {
                     velocity.x = 0.;
                     velocity.y = 0.;
                 }
             }
-            AIType::Dead => {
-                velocity.x = 0.;
-                velocity.y = 0.;
-            }
         }
-    }
+    });
 }
 
-pub fn player_attack_system(world: &mut World) {
-    let dt = get_frame_time();
+/// Where the player's attack checks an enemy's body, from its position. Not
+/// quite the hurtbox enemies attack with.
+const ATTACK_HIT_OFFSET: f32 = 10.0;
+
+pub fn player_attack_system(world: &mut WorldCell) {
+    let dt = world.resource::<Time>().delta;
 
     // u enemy cooldowns first
     for enemy in world.query::<&mut Enemy>() {
@@ -238,7 +317,7 @@ pub fn player_attack_system(world: &mut World) {
     }
 
     let (attack_rect, is_attacking) =
-        if let Some((position, player)) = world.query::<(&Position, &Player)>().iter().next() {
+        if let Some((position, player)) = world.query::<(&Position, &Player)>().get_single() {
             let attack_rect = match player.last_direction {
                 Direction::Right => Rect::new(position.x + 30.0, position.y + 24.0, 15.0, 20.0),
                 Direction::Left => Rect::new(position.x + 3.0, position.y + 24.0, 15.0, 20.0),
@@ -251,31 +330,56 @@ pub fn player_attack_system(world: &mut World) {
         };
 
     if is_attacking {
-        for (enemy_pos, enemy_collider, enemy) in
-            world.query::<(&Position, &Collider, &mut Enemy)>()
-        {
-            if !matches!(enemy.ai_type, AIType::Dead) {
-                let enemy_rect = Rect::new(
-                    enemy_pos.x + 10.0,
-                    enemy_pos.y + 10.0,
-                    enemy_collider.collision_size.x,
-                    enemy_collider.collision_size.y,
-                );
-
-                if attack_rect.overlaps(&enemy_rect) && enemy.hit_cooldown <= 0.0 {
-                    enemy.ai_type = AIType::Dead;
-                }
+        let mut commands = Commands::new();
+        let mut killed = Vec::new();
+
+        // the hit box sits a bit off the hurtbox, so look around it and
+        // check the exact box below
+        let search_rect = Rect::new(
+            attack_rect.x - ATTACK_HIT_OFFSET,
+            attack_rect.y - ATTACK_HIT_OFFSET,
+            attack_rect.w + ATTACK_HIT_OFFSET * 2.0,
+            attack_rect.h + ATTACK_HIT_OFFSET * 2.0,
+        );
+        for entity in world.hurtboxes_overlapping(search_rect) {
+            let Some((enemy_pos, enemy_collider, enemy)) =
+                world.query_one::<(&Position, &Collider, &mut Enemy)>(entity)
+            else {
+                continue;
+            };
+            let enemy_rect = Rect::new(
+                enemy_pos.x + ATTACK_HIT_OFFSET,
+                enemy_pos.y + ATTACK_HIT_OFFSET,
+                enemy_collider.collision_size.x,
+                enemy_collider.collision_size.y,
+            );
+            if !attack_rect.overlaps(&enemy_rect) {
+                continue;
+            }
+
+            if !matches!(enemy.ai_type, AIType::Dead) && enemy.hit_cooldown <= 0.0 {
+                enemy.ai_type = AIType::Dead;
+                killed.push(EnemyKilled {
+                    enemy: entity,
+                    position: Vec2::new(enemy_pos.x, enemy_pos.y),
+                });
+
+                // dead enemies don't collide or hit anymore
+                commands.remove::<Collider>(entity);
             }
         }
-    }
-}
 
-fn is_player_hit(player_rect: &Rect, enemy_rect: &Rect) -> bool {
-    player_rect.overlaps(&enemy_rect)
+        world.defer(commands);
+
+        let mut events = world.event_writer::<EnemyKilled>();
+        for event in killed {
+            events.send(event);
+        }
+    }
 }
 
-pub fn hit_systems(world: &mut World) {
-    let dt = get_frame_time();
+pub fn hit_systems(world: &mut WorldCell) {
+    let dt = world.resource::<Time>().delta;
 
     for player in world.query::<&mut Player>() {
         if player.hit_cooldown_timer > 0.0 {
@@ -283,90 +387,296 @@ pub fn hit_systems(world: &mut World) {
         }
     }
 
-    let mut player_was_hit = false;
-
-    let (player_rect, cooldown_timer) =
-        if let Some((position, player)) = world.query::<(&Position, &Player)>().iter().next() {
-            (
-                Rect::new(position.x + 18., position.y + 20., 13., 22.),
-                player.hit_cooldown_timer,
-            )
-        } else {
-            return;
-        };
-
-    for (position, collider, enemy) in world.query::<(&Position, &Collider, &Enemy)>() {
-        if enemy.attacking && cooldown_timer <= 0.0 {
-            let enemy_rect = Rect::new(
-                position.x + 8.0,
-                position.y + 8.0,
-                collider.collision_size.x,
-                collider.collision_size.y,
-            );
-            if is_player_hit(&player_rect, &enemy_rect) {
-                player_was_hit = true;
+    let mut hit_by = None;
+
+    let (player, player_rect, cooldown_timer) = if let Some((entity, position, player)) =
+        world.query::<(Entity, &Position, &Player)>().get_single()
+    {
+        (
+            entity,
+            Rect::new(position.x + 18., position.y + 20., 13., 22.),
+            player.hit_cooldown_timer,
+        )
+    } else {
+        return;
+    };
+
+    if cooldown_timer <= 0.0 {
+        for entity in world.hurtboxes_overlapping(player_rect) {
+            if world
+                .get_component::<Enemy>(entity)
+                .is_some_and(|enemy| enemy.attacking)
+            {
+                hit_by = Some(entity);
                 break;
             }
         }
     }
 
-    if player_was_hit {
+    if let Some(enemy) = hit_by {
         for player in world.query::<&mut Player>() {
             player.hit_cooldown_timer = player.hit_cooldown_duration;
         }
+
+        world
+            .event_writer::<PlayerHit>()
+            .send(PlayerHit { player, enemy });
     }
 }
 
-pub fn movement_systems(world: &mut World, map: &Map) {
-    let dt = get_frame_time();
-    for (position, velocity, collider) in world.query::<(&mut Position, &Velocity, &Collider)>() {
-        let new_pos = Vec2::new(position.x + velocity.x * dt, position.y + velocity.y * dt);
-        let collision_box = Rect::new(
-            new_pos.x + collider.collision_offset.x,
-            new_pos.y + collider.collision_offset.y,
-            collider.collision_size.x,
-            collider.collision_size.y,
-        );
+pub fn movement_systems(world: &mut WorldCell) {
+    let dt = world.resource::<Time>().delta;
 
-        if !check_collision_with_objects(collision_box, &map) {
-            let clamped_x = (new_pos.x + collider.sprite_padding.x)
-                .clamp(0.0, WORLD_WIDTH - collider.visible_size.x);
-            let clamped_y = (new_pos.y + collider.sprite_padding.y)
-                .clamp(0.0, WORLD_HEIGHT - collider.visible_size.y);
+    world.resource_scope::<SpatialIndex, _>(|world, index| {
+        for (position, velocity, collider, contact) in
+            world.query::<(&mut Position, &Velocity, &Collider, Option<&mut Contact>)>()
+        {
+            let from = Vec2::new(position.x, position.y);
+            let delta = Vec2::new(velocity.x * dt, velocity.y * dt);
+            let (moved, mut hit) = slide(index, collider, from, delta);
+
+            let min = -collider.sprite_padding;
+            let max = Vec2::new(WORLD_WIDTH, WORLD_HEIGHT)
+                - collider.visible_size
+                - collider.sprite_padding;
+            let clamped = moved.clamp(min, max);
+            hit.left |= moved.x < min.x;
+            hit.right |= moved.x > max.x;
+            hit.up |= moved.y < min.y;
+            hit.down |= moved.y > max.y;
+
+            position.x = clamped.x;
+            position.y = clamped.y;
+            if let Some(contact) = contact {
+                *contact = hit;
+            }
+        }
+    });
+}
+
+/// Moves along x, then along y, each as far as the map allows, so running
+/// into a wall at an angle slides along it instead of stopping dead.
+fn slide(index: &SpatialIndex, collider: &Collider, from: Vec2, delta: Vec2) -> (Vec2, Contact) {
+    let mut contact = Contact::default();
 
-            position.x = clamped_x - collider.sprite_padding.x;
-            position.y = clamped_y - collider.sprite_padding.y;
+    let step = Vec2::new(delta.x, 0.0);
+    let fraction = free_fraction(index, collider, from, step);
+    if fraction < 1.0 {
+        contact.left = delta.x < 0.0;
+        contact.right = delta.x > 0.0;
+    }
+    let position = from + step * fraction;
+
+    let step = Vec2::new(0.0, delta.y);
+    let fraction = free_fraction(index, collider, position, step);
+    if fraction < 1.0 {
+        contact.up = delta.y < 0.0;
+        contact.down = delta.y > 0.0;
+    }
+
+    (position + step * fraction, contact)
+}
+
+/// How much of `step` can be taken before the collision box hits the map,
+/// binary searched since map shapes can be round.
+fn free_fraction(index: &SpatialIndex, collider: &Collider, from: Vec2, step: Vec2) -> f32 {
+    let blocked =
+        |fraction: f32| index.collides_with_map(collider.collision_box(from + step * fraction));
+    if step == Vec2::ZERO || !blocked(1.0) {
+        return 1.0;
+    }
+
+    let (mut free, mut hit) = (0.0, 1.0);
+    for _ in 0..10 {
+        let middle = (free + hit) / 2.0;
+        if blocked(middle) {
+            hit = middle;
+        } else {
+            free = middle;
         }
     }
+    free
 }
 
-pub fn check_collision_with_objects(player_box: Rect, map: &Map) -> bool {
-    if let Some(collision_layer) = map.layers.get("collisions") {
-        for object in &collision_layer.objects {
-            let object_rect = Rect::new(
-                object.world_x,
-                object.world_y,
-                object.world_w,
-                object.world_h,
-            );
-            if player_box.overlaps(&object_rect) {
-                return true;
-            }
+/// Runs first in every fixed step, so rendering knows where entities were
+/// before it.
+pub fn store_previous_position_system(world: &mut WorldCell) {
+    for (position, previous) in world.query::<(&Position, &mut PreviousPosition)>() {
+        previous.x = position.x;
+        previous.y = position.y;
+    }
+}
+
+/// Fraction of a fixed step since the last one, 1 (draw the latest
+/// positions) when the game doesn't use a fixed step.
+fn fixed_alpha(world: &World) -> f32 {
+    world
+        .get_resource::<FixedTime>()
+        .map_or(1.0, |fixed| fixed.alpha())
+}
+
+/// Writes the world `Position` of every entity with a `LocalPosition`, walking
+/// down from the root entities so children see their parent's final position.
+pub fn transform_propagate_system(world: &mut World) {
+    let mut stack: Vec<(Entity, Vec2)> = world
+        .query_filtered::<(Entity, &Position), (With<Children>, Without<Parent>)>()
+        .map(|(entity, position)| (entity, Vec2::new(position.x, position.y)))
+        .collect();
+
+    while let Some((parent, parent_position)) = stack.pop() {
+        for index in 0..world.children(parent).len() {
+            let child = world.children(parent)[index];
+
+            let position = match world.get_component::<LocalPosition>(child) {
+                Some(local) => {
+                    let position = parent_position + Vec2::new(local.x, local.y);
+                    match world.get_component_mut::<Position>(child) {
+                        Some(world_position) => {
+                            world_position.x = position.x;
+                            world_position.y = position.y;
+                        }
+                        None => world.add_component_to_entity(
+                            child,
+                            Position {
+                                x: position.x,
+                                y: position.y,
+                            },
+                        ),
+                    }
+                    position
+                }
+                // children without an offset move on their own
+                None => match world.get_component::<Position>(child) {
+                    Some(position) => Vec2::new(position.x, position.y),
+                    None => parent_position,
+                },
+            };
+
+            stack.push((child, position));
         }
     }
-    false
 }
 
-pub fn camera_systems(world: &mut World, resources: &mut Resources) {
-    for (position, _) in world.query::<(&Position, &Player)>() {
+pub fn camera_systems(world: &mut World) {
+    let alpha = fixed_alpha(world);
+    if let Some(position) = world
+        .query_filtered::<(&Position, Option<&PreviousPosition>), With<Player>>()
+        .get_single()
+        .map(|(position, previous)| position.interpolated(previous, alpha))
+    {
         let target_x = position.x + 24.0; // center on player
         let target_y = position.y + 24.0;
 
         let clamped_x = target_x.clamp(256.0, WORLD_WIDTH - 256.0);
         let clamped_y = target_y.clamp(144.0, WORLD_HEIGHT - 144.0);
-        resources.camera.target = Vec2::new(clamped_x, clamped_y);
+        let target = Vec2::new(clamped_x, clamped_y);
+
+        world.resource_mut::<Camera2D>().target = target;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use macroquad::math::{vec2, Rect, Vec2};
+
+    use super::{free_fraction, slide, transform_propagate_system};
+    use crate::{
+        components::{
+            collider::Collider,
+            contact::Contact,
+            position::{LocalPosition, Position},
+        },
+        shape::Shape,
+        spatial::{SpatialIndex, CELL_SIZE},
+        world::World,
+    };
+
+    fn collider() -> Collider {
+        Collider {
+            collision_offset: Vec2::ZERO,
+            collision_size: vec2(10.0, 10.0),
+            sprite_padding: Vec2::ZERO,
+            visible_size: vec2(10.0, 10.0),
+        }
+    }
+
+    /// A wall from x 100 to 110, running down the whole test area.
+    fn wall() -> SpatialIndex {
+        let mut index = SpatialIndex::new(CELL_SIZE);
+        index.insert_static(Shape::Rect(Rect::new(100.0, 0.0, 10.0, 200.0)));
+        index
+    }
+
+    #[test]
+    fn free_fraction_stops_at_the_wall() {
+        let index = wall();
+        let collider = collider();
+
+        // 10 of the 30 fit before the box reaches x 100
+        let fraction = free_fraction(&index, &collider, vec2(80.0, 50.0), vec2(30.0, 0.0));
+        assert!((fraction - 1.0 / 3.0).abs() < 0.01, "{}", fraction);
+        assert_eq!(
+            free_fraction(&index, &collider, vec2(80.0, 50.0), vec2(-30.0, 0.0)),
+            1.0
+        );
+    }
+
+    #[test]
+    fn diagonal_move_into_a_wall_slides_along_it() {
+        let index = wall();
+
+        let (moved, contact) = slide(&index, &collider(), vec2(80.0, 50.0), vec2(30.0, 20.0));
+
+        assert!(moved.x > 89.9 && moved.x < 90.0, "{}", moved.x);
+        assert_eq!(moved.y, 70.0);
+        assert_eq!(
+            contact,
+            Contact {
+                right: true,
+                ..Contact::default()
+            }
+        );
+
+        let (moved, contact) = slide(&index, &collider(), vec2(120.0, 50.0), vec2(-30.0, -20.0));
+
+        assert!(moved.x > 110.0 && moved.x < 110.1, "{}", moved.x);
+        assert_eq!(moved.y, 30.0);
+        assert_eq!(
+            contact,
+            Contact {
+                left: true,
+                ..Contact::default()
+            }
+        );
+    }
 
-        set_camera(&resources.camera);
-        return; // player only
+    #[test]
+    fn children_follow_their_parents_two_levels_down() {
+        let mut world = World::new();
+        let root = world
+            .spawn_entity()
+            .with(Position { x: 100.0, y: 50.0 })
+            .id();
+        let child = world
+            .spawn_entity()
+            .with(LocalPosition { x: 10.0, y: -5.0 })
+            .id();
+        let grandchild = world
+            .spawn_entity()
+            .with(LocalPosition { x: 1.0, y: 2.0 })
+            .id();
+        world.set_parent(child, root);
+        world.set_parent(grandchild, child);
+
+        transform_propagate_system(&mut world);
+        let position = world.get_component::<Position>(grandchild).unwrap();
+        assert_eq!((position.x, position.y), (111.0, 47.0));
+
+        world.get_component_mut::<Position>(root).unwrap().x = 0.0;
+        transform_propagate_system(&mut world);
+        let position = world.get_component::<Position>(child).unwrap();
+        assert_eq!((position.x, position.y), (10.0, 45.0));
+        let position = world.get_component::<Position>(grandchild).unwrap();
+        assert_eq!((position.x, position.y), (11.0, 47.0));
     }
 }
@@ -1,24 +1,38 @@
-use std::{any::Any, collections::HashMap, error::Error};
+use std::{
+    any::{type_name, Any, TypeId},
+    cell::UnsafeCell,
+    collections::HashMap,
+    error::Error,
+};
 
 use macroquad::{
     math::{Rect, Vec2},
-    prelude::animation::{AnimatedSprite, Animation},
     texture::Texture2D,
 };
 use macroquad_tiled::Object;
 
 use crate::{
+    commands::Commands,
     components::{
         collider::Collider,
-        enemy::{animated_skeleton, animated_slime, Enemy},
+        contact::Contact,
+        health::Health,
+        hierarchy::{Children, Parent},
+        name::Name,
         player::Player,
-        position::Position,
-        sprite::Sprite,
+        position::{Position, PreviousPosition},
+        rng::stream_key,
+        sprite::{AnimationDef, Sprite, SpriteSheet},
         velocity::Velocity,
     },
-    entity::entity::Entity,
-    query::ComponentQuery,
-    resources,
+    entity::entity::{Entities, Entity},
+    events::{EventReader, EventWriter, Events},
+    filter::QueryFilter,
+    query::{ComponentQuery, QueryIter},
+    resources::{Assets, EnemyRegistry, GameRng},
+    save::{self, Persist, SaveRegistry},
+    spatial::SpatialIndex,
+    storage::{check_tick, ColumnPtr, ComponentStorage, StorageType},
 };
 
 pub static WORLD_WIDTH: f32 = 960.0;
@@ -26,13 +40,29 @@ pub static WORLD_HEIGHT: f32 = 512.0;
 
 trait ComponentVec {
     fn push_none(&mut self);
+    fn remove(&mut self, index: usize);
+    fn contains(&self, index: usize) -> bool;
+    fn check_change_ticks(&mut self, change_tick: u32);
+    fn type_name(&self) -> &'static str;
     fn as_any(&self) -> &dyn Any;
     fn as_any_mut(&mut self) -> &mut dyn Any;
 }
 
-impl<T: 'static> ComponentVec for Vec<Option<T>> {
+impl<T: 'static> ComponentVec for ComponentStorage<T> {
     fn push_none(&mut self) {
-        self.push(None);
+        ComponentStorage::push_none(self);
+    }
+    fn remove(&mut self, index: usize) {
+        ComponentStorage::remove(self, index);
+    }
+    fn contains(&self, index: usize) -> bool {
+        self.get(index).is_some()
+    }
+    fn check_change_ticks(&mut self, change_tick: u32) {
+        ComponentStorage::check_change_ticks(self, change_tick);
+    }
+    fn type_name(&self) -> &'static str {
+        type_name::<T>()
     }
     fn as_any(&self) -> &dyn Any {
         self as &dyn Any
@@ -44,132 +74,661 @@ impl<T: 'static> ComponentVec for Vec<Option<T>> {
 
 pub struct EntityBuilder<'a> {
     world: &'a mut World,
-    entity_id: usize,
+    entity: Entity,
 }
 
 impl<'a> EntityBuilder<'a> {
-    pub fn new(world: &'a mut World, entity_id: usize) -> Self {
-        Self { world, entity_id }
+    pub fn new(world: &'a mut World, entity: Entity) -> Self {
+        Self { world, entity }
     }
 
     pub fn with<ComponentType: 'static>(self, component: ComponentType) -> Self {
-        self.world
-            .add_component_to_entity(self.entity_id, component);
+        self.world.add_component_to_entity(self.entity, component);
+        self
+    }
+
+    pub fn without<ComponentType: 'static>(self) -> Self {
+        self.world.remove_component::<ComponentType>(self.entity);
         self
     }
+
+    /// Removes the component and hands it back, e.g. to move it elsewhere.
+    pub fn take<ComponentType: 'static>(&mut self) -> Option<ComponentType> {
+        self.world.remove_component::<ComponentType>(self.entity)
+    }
+
+    pub fn has<ComponentType: 'static>(&self) -> bool {
+        self.world.has::<ComponentType>(self.entity)
+    }
+
+    /// Attaches the entity to `parent`, see `World::set_parent`.
+    pub fn child_of(self, parent: Entity) -> Self {
+        self.world.set_parent(self.entity, parent);
+        self
+    }
+
+    pub fn id(&self) -> Entity {
+        self.entity
+    }
 }
 
 pub struct World {
-    pub entities_count: Entity,
-    components_vec: Vec<Box<dyn ComponentVec>>,
+    entities: Entities,
+    components: HashMap<TypeId, Box<dyn ComponentVec>>,
+    default_storage: StorageType,
+    change_tick: u32,
+    last_change_tick: u32,
+    commands: Commands,
+    resources: HashMap<TypeId, Box<dyn Any>>, // UnsafeCell<R> by TypeId of R
+    event_updaters: Vec<fn(&mut World)>,
+    names: HashMap<String, Vec<Entity>>,
+    tiled_objects: HashMap<u32, Entity>,
+    tiled_ids: HashMap<Entity, u32>, // reverse of `tiled_objects`
+    in_fixed_update: bool,
+}
+
+impl Default for World {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl World {
     pub fn new() -> Self {
         Self {
-            entities_count: 0,
-            components_vec: Vec::new(),
+            entities: Entities::default(),
+            components: HashMap::new(),
+            default_storage: StorageType::Dense,
+            change_tick: 1,
+            last_change_tick: 0,
+            commands: Commands::new(),
+            resources: HashMap::new(),
+            event_updaters: Vec::new(),
+            names: HashMap::new(),
+            tiled_objects: HashMap::new(),
+            tiled_ids: HashMap::new(),
+            in_fixed_update: false,
+        }
+    }
+
+    /// Storage used for component types that weren't registered explicitly.
+    pub fn with_default_storage(storage_type: StorageType) -> Self {
+        Self {
+            default_storage: storage_type,
+            ..Self::new()
+        }
+    }
+
+    /// Picks the storage backend for a component type. Components already
+    /// stored are moved over, so this can be called at any time.
+    pub fn register_component<ComponentType: 'static>(&mut self, storage_type: StorageType) {
+        match self.column_mut::<ComponentType>() {
+            Some(column) => column.convert(storage_type),
+            None => {
+                let column =
+                    ComponentStorage::<ComponentType>::new(storage_type, self.entities.len());
+                self.components
+                    .insert(TypeId::of::<ComponentType>(), Box::new(column));
+            }
+        }
+    }
+
+    pub fn add_entity(&mut self) -> Entity {
+        let (entity, new_slot) = self.entities.alloc();
+
+        // increase capacity if components have been initialized,
+        // reused slots were already cleared on despawn
+        if new_slot {
+            for component_vec in self.components.values_mut() {
+                component_vec.push_none();
+            }
         }
+
+        entity
     }
 
-    pub fn add_entity(&mut self) -> usize {
-        let entity_id = self.entities_count;
+    pub fn spawn_entity(&mut self) -> EntityBuilder<'_> {
+        let entity = self.add_entity();
+        EntityBuilder::new(self, entity)
+    }
 
-        // increase capacity if components have been initialized
-        for component_vec in self.components_vec.iter_mut() {
-            component_vec.push_none();
+    /// Builder for adding and removing components on an existing entity.
+    /// Returns `None` if the handle is stale.
+    pub fn entity_mut(&mut self, entity: Entity) -> Option<EntityBuilder<'_>> {
+        if !self.entities.is_alive(entity) {
+            return None;
         }
 
-        self.entities_count += 1;
-        entity_id
+        Some(EntityBuilder::new(self, entity))
+    }
+
+    /// Current change tick. Components inserted or borrowed mutably record it,
+    /// which is what `Added` and `Changed` compare against.
+    pub fn change_tick(&self) -> u32 {
+        self.change_tick
+    }
+
+    /// `Added`/`Changed` in queries on the world match ticks newer than this.
+    /// The schedule sets it to each exclusive system's last run.
+    pub fn last_change_tick(&self) -> u32 {
+        self.last_change_tick
+    }
+
+    pub(crate) fn set_last_change_tick(&mut self, tick: u32) {
+        self.last_change_tick = tick;
+    }
+
+    /// Moves on to a new change tick and returns it, so what happens from
+    /// now on can be told apart from what happened before.
+    pub fn increment_change_tick(&mut self) -> u32 {
+        self.change_tick = self.change_tick.wrapping_add(1);
+        self.change_tick
+    }
+
+    /// Starts a new change detection window, call once per frame after all
+    /// systems ran. `Added`/`Changed` in queries on the world only match what
+    /// happened since.
+    pub fn clear_trackers(&mut self) {
+        self.last_change_tick = self.change_tick;
+        self.increment_change_tick();
+    }
+
+    /// Clamps every component tick that's gotten too old to compare, so it
+    /// doesn't wrap around and show up in `Changed` again. Has to run at least
+    /// every `CHECK_TICK_THRESHOLD` ticks, `Schedule::run` takes care of it.
+    pub fn check_change_ticks(&mut self) {
+        let change_tick = self.change_tick;
+        for component_vec in self.components.values_mut() {
+            component_vec.check_change_ticks(change_tick);
+        }
+        check_tick(&mut self.last_change_tick, change_tick);
+    }
+
+    /// Swaps the buffers of every event type registered with `add_event`, call
+    /// once per frame after all systems ran.
+    pub fn update_events(&mut self) {
+        for update in self.event_updaters.clone() {
+            update(self);
+        }
+    }
+
+    /// Queues commands recorded by a system until the next `apply_commands`.
+    pub fn defer(&mut self, mut commands: Commands) {
+        self.commands.append(&mut commands);
+    }
+
+    /// Sync point: applies every deferred command in the order it was queued.
+    pub fn apply_commands(&mut self) {
+        // commands can defer more commands, keep going until nothing is left
+        while !self.commands.is_empty() {
+            let mut commands = std::mem::take(&mut self.commands);
+            commands.apply(self);
+        }
+    }
+
+    /// Removes the entity, all of its components and all of its children.
+    /// The slot goes on the free list and gets reused by a later spawn with a
+    /// bumped generation. Returns false if the handle was already stale.
+    pub fn despawn(&mut self, entity: Entity) -> bool {
+        if !self.entities.is_alive(entity) {
+            return false;
+        }
+
+        self.remove_parent(entity);
+        if let Some(children) = self.remove_component::<Children>(entity) {
+            for child in children.0 {
+                self.despawn(child);
+            }
+        }
+
+        self.unindex_name(entity);
+        self.unmap_tiled_object(entity);
+        self.entities.free(entity);
+
+        for component_vec in self.components.values_mut() {
+            component_vec.remove(entity.index);
+        }
+
+        true
+    }
+
+    /// Attaches `child` to `parent`, detaching it from its previous parent.
+    /// Returns false if either handle is stale or `child` is `parent` or one
+    /// of its ancestors, since that would make a loop.
+    pub fn set_parent(&mut self, child: Entity, parent: Entity) -> bool {
+        if !self.is_alive(child) || !self.is_alive(parent) {
+            return false;
+        }
+
+        let mut ancestor = Some(parent);
+        while let Some(current) = ancestor {
+            if current == child {
+                return false;
+            }
+            ancestor = self.parent(current);
+        }
+
+        self.remove_parent(child);
+        self.add_component_to_entity(child, Parent(parent));
+        match self.get_component_mut::<Children>(parent) {
+            Some(children) => children.0.push(child),
+            None => self.add_component_to_entity(parent, Children(vec![child])),
+        }
+
+        true
+    }
+
+    /// Detaches the entity from its parent, returning the parent it had.
+    pub fn remove_parent(&mut self, child: Entity) -> Option<Entity> {
+        let Parent(parent) = self.remove_component::<Parent>(child)?;
+
+        if let Some(children) = self.get_component_mut::<Children>(parent) {
+            children.0.retain(|&other| other != child);
+            if children.0.is_empty() {
+                self.remove_component::<Children>(parent);
+            }
+        }
+
+        Some(parent)
+    }
+
+    pub fn parent(&self, entity: Entity) -> Option<Entity> {
+        self.get_component::<Parent>(entity).map(|parent| parent.0)
+    }
+
+    pub fn children(&self, entity: Entity) -> &[Entity] {
+        self.get_component::<Children>(entity)
+            .map_or(&[], |children| &children.0)
+    }
+
+    /// First entity spawned with this `Name`, e.g. `find_by_name("player")`.
+    pub fn find_by_name(&self, name: &str) -> Option<Entity> {
+        self.find_all_by_name(name).first().copied()
+    }
+
+    /// Every entity with this `Name`, in the order they got it.
+    pub fn find_all_by_name(&self, name: &str) -> &[Entity] {
+        self.names.get(name).map_or(&[], |entities| entities)
+    }
+
+    fn unindex_name(&mut self, entity: Entity) {
+        let Some(Name(name)) = self
+            .column::<Name>()
+            .and_then(|names| names.get(entity.index))
+        else {
+            return;
+        };
+        let name = name.clone();
+
+        if let Some(entities) = self.names.get_mut(&name) {
+            entities.retain(|&other| other != entity);
+            if entities.is_empty() {
+                self.names.remove(&name);
+            }
+        }
+    }
+
+    /// Entity spawned from the Tiled object with this id, if it's still alive.
+    pub fn tiled_object(&self, id: u32) -> Option<Entity> {
+        self.tiled_objects
+            .get(&id)
+            .copied()
+            .filter(|&entity| self.is_alive(entity))
+    }
+
+    /// Tiled object id the entity was spawned from, if any.
+    pub fn tiled_object_id(&self, entity: Entity) -> Option<u32> {
+        self.tiled_ids.get(&entity).copied()
+    }
+
+    pub(crate) fn map_tiled_object(&mut self, id: u32, entity: Entity) {
+        self.unmap_tiled_object(entity);
+        if let Some(previous) = self.tiled_objects.insert(id, entity) {
+            self.tiled_ids.remove(&previous);
+        }
+        self.tiled_ids.insert(entity, id);
+    }
+
+    fn unmap_tiled_object(&mut self, entity: Entity) {
+        if let Some(id) = self.tiled_ids.remove(&entity) {
+            self.tiled_objects.remove(&id);
+        }
+    }
+
+    /// Marks a component type as part of save files under `key`, which has to
+    /// stay the same between versions of the game.
+    pub fn register_persistent<T: Persist>(&mut self, key: &'static str) {
+        if self.get_resource::<SaveRegistry>().is_none() {
+            self.insert_resource(SaveRegistry::default());
+        }
+        self.resource_mut::<SaveRegistry>().register::<T>(key);
+    }
+
+    /// Writes every entity with its persistent components to a JSON file.
+    pub fn save(&self, path: &str) -> Result<(), Box<dyn Error>> {
+        save::save_world(self, path)
+    }
+
+    /// Whether `rect` touches the map's collision layer.
+    pub fn collides_with_map(&self, rect: Rect) -> bool {
+        self.get_resource::<SpatialIndex>()
+            .is_some_and(|index| index.collides_with_map(rect))
+    }
+
+    /// Entities whose hurtbox overlaps `rect`, as of the start of the fixed
+    /// step.
+    pub fn hurtboxes_overlapping(&self, rect: Rect) -> Vec<Entity> {
+        self.get_resource::<SpatialIndex>()
+            .map_or_else(Vec::new, |index| index.hurtboxes_overlapping(rect))
+    }
+
+    /// Hash of everything a save would contain, to tell whether two runs
+    /// ended in the same state.
+    pub fn checksum(&self) -> Result<u64, Box<dyn Error>> {
+        Ok(stream_key(&[save::snapshot_json(self)?.as_bytes()]))
+    }
+
+    /// Replaces all entities with the ones saved in `path`.
+    pub fn load(&mut self, path: &str) -> Result<(), Box<dyn Error>> {
+        save::load_world(self, path)
+    }
+
+    pub fn is_alive(&self, entity: Entity) -> bool {
+        self.entities.is_alive(entity)
+    }
+
+    /// Number of slots ever allocated, including free ones.
+    pub fn entities_count(&self) -> usize {
+        self.entities.len()
+    }
+
+    pub fn alive_count(&self) -> usize {
+        self.entities.alive_count()
     }
 
-    pub fn spawn_entity(&mut self) -> EntityBuilder {
-        let entity_id = self.add_entity();
-        EntityBuilder::new(self, entity_id)
+    pub fn entities(&self) -> impl Iterator<Item = Entity> + '_ {
+        self.entities.iter()
     }
 
+    pub fn entity_allocator(&self) -> &Entities {
+        &self.entities
+    }
+
+    /// Adds the component to the entity, replacing any previous one of the
+    /// same type. Stale handles are ignored.
     pub fn add_component_to_entity<ComponentType: 'static>(
         &mut self,
         entity: Entity,
         component: ComponentType,
     ) {
-        // iterate through components vec and find the component of the same type
-        for component_vec in self.components_vec.iter_mut() {
-            if let Some(component_vec) = component_vec
-                .as_any_mut()
-                .downcast_mut::<Vec<Option<ComponentType>>>()
-            {
-                component_vec[entity] = Some(component);
-                return;
-            }
+        if !self.entities.is_alive(entity) {
+            return;
+        }
+
+        if let Some(name) = (&component as &dyn Any).downcast_ref::<Name>() {
+            self.unindex_name(entity);
+            self.names.entry(name.0.clone()).or_default().push(entity);
+        }
+
+        let change_tick = self.change_tick;
+        if let Some(column) = self.column_mut::<ComponentType>() {
+            column.insert(entity.index, component, change_tick);
+            return;
+        }
+
+        // if not found then create a new column
+        let mut column =
+            ComponentStorage::<ComponentType>::new(self.default_storage, self.entities.len());
+        column.insert(entity.index, component, change_tick);
+
+        self.components
+            .insert(TypeId::of::<ComponentType>(), Box::new(column));
+    }
+
+    /// Removes the component from the entity and returns it, if it had one.
+    pub fn remove_component<ComponentType: 'static>(
+        &mut self,
+        entity: Entity,
+    ) -> Option<ComponentType> {
+        if !self.entities.is_alive(entity) {
+            return None;
         }
 
-        // if not found then create a new component vec
-        let mut new_component_vec: Vec<Option<ComponentType>> =
-            Vec::with_capacity(self.entities_count);
-        for _ in 0..self.entities_count {
-            new_component_vec.push(None);
+        if TypeId::of::<ComponentType>() == TypeId::of::<Name>() {
+            self.unindex_name(entity);
         }
 
-        new_component_vec[entity] = Some(component);
+        self.column_mut::<ComponentType>()?.remove(entity.index)
+    }
 
-        self.components_vec.push(Box::new(new_component_vec));
+    pub fn has<ComponentType: 'static>(&self, entity: Entity) -> bool {
+        self.get_component::<ComponentType>(entity).is_some()
+    }
+
+    /// Type names of every component the entity currently has, sorted.
+    pub fn component_names(&self, entity: Entity) -> Vec<&'static str> {
+        if !self.entities.is_alive(entity) {
+            return Vec::new();
+        }
+
+        let mut names: Vec<&'static str> = self
+            .components
+            .values()
+            .filter(|component_vec| component_vec.contains(entity.index))
+            .map(|component_vec| component_vec.type_name())
+            .collect();
+        names.sort_unstable();
+        names
     }
 
     pub fn get_component<ComponentType: 'static>(&self, entity: Entity) -> Option<&ComponentType> {
-        for component_vec in self.components_vec.iter() {
-            if let Some(component_vec) = component_vec
-                .as_any()
-                .downcast_ref::<Vec<Option<ComponentType>>>()
-            {
-                return component_vec[entity].as_ref();
-            }
+        if !self.entities.is_alive(entity) {
+            return None;
         }
 
-        None
+        self.column::<ComponentType>()?.get(entity.index)
     }
 
     pub fn get_component_mut<ComponentType: 'static>(
         &mut self,
         entity: Entity,
     ) -> Option<&mut ComponentType> {
-        for component_vec in self.components_vec.iter_mut() {
-            if let Some(component_vec) = component_vec
-                .as_any_mut()
-                .downcast_mut::<Vec<Option<ComponentType>>>()
-            {
-                return component_vec[entity].as_mut();
-            }
+        if !self.entities.is_alive(entity) {
+            return None;
+        }
+
+        let change_tick = self.change_tick;
+        self.column_mut::<ComponentType>()?
+            .get_mut(entity.index, change_tick)
+    }
+
+    /// The whole storage for a component type, indexed by entity slot.
+    pub fn column<ComponentType: 'static>(&self) -> Option<&ComponentStorage<ComponentType>> {
+        self.components
+            .get(&TypeId::of::<ComponentType>())?
+            .as_any()
+            .downcast_ref::<ComponentStorage<ComponentType>>()
+    }
+
+    /// Raw view of a column through a shared borrow, see `ColumnPtr`.
+    pub(crate) fn column_ptr<ComponentType: 'static>(&self) -> Option<ColumnPtr<ComponentType>> {
+        self.column::<ComponentType>()
+            .map(|column| column.as_ptr(self.change_tick))
+    }
+
+    pub fn column_mut<ComponentType: 'static>(
+        &mut self,
+    ) -> Option<&mut ComponentStorage<ComponentType>> {
+        self.components
+            .get_mut(&TypeId::of::<ComponentType>())?
+            .as_any_mut()
+            .downcast_mut::<ComponentStorage<ComponentType>>()
+    }
+
+    /// Stores a global resource (map, camera, time...), replacing any previous
+    /// one of the same type.
+    pub fn insert_resource<R: 'static>(&mut self, resource: R) {
+        self.resources
+            .insert(TypeId::of::<R>(), Box::new(UnsafeCell::new(resource)));
+    }
+
+    pub fn remove_resource<R: 'static>(&mut self) -> Option<R> {
+        self.resources
+            .remove(&TypeId::of::<R>())
+            .and_then(|resource| resource.downcast::<UnsafeCell<R>>().ok())
+            .map(|resource| resource.into_inner())
+    }
+
+    pub fn has_resource<R: 'static>(&self) -> bool {
+        self.resources.contains_key(&TypeId::of::<R>())
+    }
+
+    pub fn get_resource<R: 'static>(&self) -> Option<&R> {
+        // SAFETY: through a shared world resources are only written by a
+        // parallel system that declared it, and nothing runs alongside that
+        // system that reads it
+        self.resource_ptr::<R>()
+            .map(|resource| unsafe { &*resource })
+    }
+
+    pub fn get_resource_mut<R: 'static>(&mut self) -> Option<&mut R> {
+        self.resources
+            .get_mut(&TypeId::of::<R>())?
+            .downcast_mut::<UnsafeCell<R>>()
+            .map(UnsafeCell::get_mut)
+    }
+
+    /// Raw access to a resource through a shared borrow, for parallel
+    /// systems. Whoever writes through it has to make sure nothing else uses
+    /// the resource meanwhile.
+    pub(crate) fn resource_ptr<R: 'static>(&self) -> Option<*mut R> {
+        self.resources
+            .get(&TypeId::of::<R>())?
+            .downcast_ref::<UnsafeCell<R>>()
+            .map(UnsafeCell::get)
+    }
+
+    /// Panics if the resource was never inserted, use `get_resource` if it's
+    /// optional.
+    pub fn resource<R: 'static>(&self) -> &R {
+        self.get_resource::<R>()
+            .unwrap_or_else(|| panic!("resource {} not found", type_name::<R>()))
+    }
+
+    pub fn resource_mut<R: 'static>(&mut self) -> &mut R {
+        self.get_resource_mut::<R>()
+            .unwrap_or_else(|| panic!("resource {} not found", type_name::<R>()))
+    }
+
+    /// Registers an event type, so systems can send and read it and
+    /// `update_events` keeps its buffers moving.
+    pub fn add_event<T: 'static>(&mut self) {
+        if self.has_resource::<Events<T>>() {
+            return;
         }
 
-        None
+        self.insert_resource(Events::<T>::default());
+        self.event_updaters
+            .push(|world| world.resource_mut::<Events<T>>().update());
+    }
+
+    /// Panics if the event type wasn't registered with `add_event`.
+    pub fn event_writer<T: 'static>(&mut self) -> EventWriter<'_, T> {
+        EventWriter::new(self.resource_mut::<Events<T>>())
+    }
+
+    /// Panics if the event type wasn't registered with `add_event`, or when
+    /// called from FixedUpdate (see `Events`).
+    pub fn event_reader<T: 'static>(&self) -> EventReader<'_, T> {
+        self.check_event_read::<T>();
+        EventReader::new(self.resource::<Events<T>>())
+    }
+
+    /// Set by the schedule while it runs FixedUpdate steps.
+    pub(crate) fn set_in_fixed_update(&mut self, in_fixed_update: bool) {
+        self.in_fixed_update = in_fixed_update;
     }
 
-    pub fn query<'a, T>(&'a mut self) -> Vec<T::Output>
+    pub(crate) fn check_event_read<T: 'static>(&self) {
+        if self.in_fixed_update {
+            panic!(
+                "{} events can't be read in FixedUpdate, it runs a varying number of times per frame, read them from Update or later",
+                type_name::<T>()
+            );
+        }
+    }
+
+    /// Takes the resource out for the duration of `f`, so it can be used while
+    /// a query borrows the world.
+    pub fn resource_scope<R: 'static, T>(&mut self, f: impl FnOnce(&mut World, &mut R) -> T) -> T {
+        let mut resource = self
+            .remove_resource::<R>()
+            .unwrap_or_else(|| panic!("resource {} not found", type_name::<R>()));
+        let result = f(self, &mut resource);
+        self.insert_resource(resource);
+        result
+    }
+
+    /// Lazy iterator over the entities having every component in `T`,
+    /// nothing is allocated and stopping early skips the rest.
+    pub fn query<'a, T>(&'a mut self) -> QueryIter<'a, T, ()>
     where
         T: ComponentQuery<'a>,
     {
-        T::find_entities(self)
+        T::find_entities::<()>(self)
+    }
+
+    /// Runs the query against a single entity, e.g.
+    /// `query_one::<(&Position, &mut Enemy)>(target)`. Returns `None` if the
+    /// handle is stale or the entity is missing one of the components.
+    pub fn query_one<'a, T>(&'a mut self, entity: Entity) -> Option<T::Item>
+    where
+        T: ComponentQuery<'a>,
+    {
+        T::find_one(self, entity)
+    }
+
+    /// Like `query`, but only visits entities matching the filter, e.g.
+    /// `query_filtered::<&Position, With<Player>>()`.
+    pub fn query_filtered<'a, T, F>(&'a mut self) -> QueryIter<'a, T, F>
+    where
+        T: ComponentQuery<'a>,
+        F: QueryFilter<'a>,
+    {
+        T::find_entities::<F>(self)
     }
 
     pub fn add_object(
         &mut self,
         object: &Object,
         core_assets: &HashMap<String, Texture2D>,
-    ) -> Result<(), Box<dyn Error>> {
-        let entity_id = self.add_entity();
+    ) -> Result<Entity, Box<dyn Error>> {
+        let entity = self.add_entity();
         let properties = &object.properties;
 
+        self.map_tiled_object(object.id, entity);
+        self.add_component_to_entity(entity, Name::new(object.name.as_str()));
+
+        let sheet = SpriteSheet {
+            frame_width: object.world_w as u32,
+            frame_height: object.world_h as u32,
+            animations: vec![AnimationDef {
+                name: object.name.to_string(),
+                row: properties["row"].parse::<u32>()?,
+                frames: properties["frames"].parse::<u32>()?,
+                fps: 8,
+            }],
+        };
+
         self.add_component_to_entity(
-            entity_id,
+            entity,
             Position {
                 x: object.world_x,
                 y: object.world_y - properties["dest_size_y"].parse::<f32>()?,
             },
         );
         self.add_component_to_entity(
-            entity_id,
+            entity,
             Sprite {
                 texture: core_assets[&format!("images/core/{}.png", object.name)].clone(),
                 source_rect: Some(Rect::new(
@@ -182,151 +741,174 @@ impl World {
                     properties["dest_size_x"].parse::<f32>()?,
                     properties["dest_size_y"].parse::<f32>()?,
                 )),
-                animation: Some(AnimatedSprite::new(
-                    object.world_w as u32,
-                    object.world_h as u32,
-                    &[Animation {
-                        name: object.name.to_string(),
-                        row: properties["row"].parse::<u32>()?,
-                        frames: properties["frames"].parse::<u32>()?,
-                        fps: 8,
-                    }],
-                    true,
-                )),
+                animation: Some(sheet.animated_sprite()),
+                sheet: Some(sheet),
                 flipped: false,
                 last_animation: 0,
+                frame_timer: 0.0,
             },
         );
 
-        Ok(())
+        Ok(entity)
     }
 
-    pub async fn spawn_enemy(&mut self, x: f32, y: f32, enemy: &str) -> Result<(), Box<dyn Error>> {
-        self.spawn_entity()
+    /// Spawns an enemy from its definition in `EnemyRegistry`, e.g. "slime"
+    /// for `assets/enemies/slime.json`. Textures come from the preloaded
+    /// `Assets`, so this can run from a deferred command without awaiting
+    /// anything.
+    pub fn spawn_enemy(&mut self, x: f32, y: f32, enemy: &str) -> Result<Entity, Box<dyn Error>> {
+        let definition = self
+            .resource::<EnemyRegistry>()
+            .get(enemy)
+            .ok_or_else(|| format!("no enemy definition '{}'", enemy))?
+            .clone();
+        let texture = self
+            .resource::<Assets>()
+            .texture(&definition.texture)
+            .ok_or_else(|| format!("texture '{}' not loaded", definition.texture))?
+            .clone();
+
+        // keyed on what the enemy is, where it spawns and how many spawned
+        // there before, not on the global spawn order, so other spawns don't
+        // shift its wander path
+        let key = stream_key(&[
+            enemy.as_bytes(),
+            &x.to_bits().to_le_bytes(),
+            &y.to_bits().to_le_bytes(),
+        ]);
+        let stream = self.resource_mut::<GameRng>().spawn_stream(key);
+
+        let sheet = definition.sheet.clone();
+        let entity = self
+            .spawn_entity()
             .with(Sprite {
-                texture: resources::load_and_set_filter(&format!("images/content/{}.png", enemy))
-                    .await?,
-                source_rect: Some(Rect::new(0.0, 0.0, 32.0, 32.0)),
-                dest_size: match enemy {
-                    "skeleton" => Some(Vec2::new(48.0, 48.0)),
-                    _ => Some(Vec2::new(32.0, 32.0)),
-                },
-                animation: match enemy {
-                    "skeleton" => animated_skeleton(),
-                    _ => animated_slime(),
-                },
+                texture,
+                source_rect: Some(Rect::new(
+                    0.0,
+                    0.0,
+                    sheet.frame_width as f32,
+                    sheet.frame_height as f32,
+                )),
+                dest_size: Some(definition.sprite_size.into()),
+                animation: Some(sheet.animated_sprite()),
+                sheet: Some(sheet),
                 flipped: false,
                 last_animation: 0,
+                frame_timer: 0.0,
             })
             .with(Position { x, y })
-            .with(Collider {
-                visible_size: match enemy {
-                    "skeleton" => Vec2::new(32.0, 32.0),
-                    _ => Vec2::new(16.0, 16.0),
-                },
-                sprite_padding: Vec2::new(8.0, 8.0),
-                collision_offset: Vec2::new(24.0, 16.0),
-                collision_size: Vec2::new(16.0, 16.0),
-            })
-            .with(match enemy {
-                "skeleton" => Enemy {
-                    attack_range: 5.,
-                    ..Default::default()
-                },
-                _ => Enemy::default(),
-            })
-            .with(Velocity { x: 8.0, y: 8.0 });
+            .with(PreviousPosition { x, y })
+            .with(Collider::from(&definition.collider))
+            .with(Contact::default())
+            .with(definition.enemy())
+            .with(Health::new(definition.health))
+            .with(Velocity { x: 8.0, y: 8.0 })
+            .with(Name::new(enemy))
+            .with(stream)
+            .id();
 
-        Ok(())
+        Ok(entity)
     }
 
-    pub async fn spawn_player(&mut self, x: f32, y: f32) -> Result<(), Box<dyn Error>> {
-        self.spawn_entity()
+    pub fn spawn_player(&mut self, x: f32, y: f32) -> Result<Entity, Box<dyn Error>> {
+        let texture = self
+            .resource::<Assets>()
+            .content
+            .get("images/content/player.png")
+            .ok_or("texture 'images/content/player.png' not loaded")?
+            .clone();
+
+        let sheet = SpriteSheet {
+            frame_width: 48,
+            frame_height: 48,
+            animations: vec![
+                AnimationDef {
+                    // 0
+                    name: "idle".to_string(),
+                    row: 0,
+                    frames: 6,
+                    fps: 4,
+                },
+                AnimationDef {
+                    // 1
+                    name: "down".to_string(),
+                    row: 3,
+                    frames: 6,
+                    fps: 4,
+                },
+                AnimationDef {
+                    // 2
+                    name: "right".to_string(),
+                    row: 4,
+                    frames: 6,
+                    fps: 4,
+                },
+                AnimationDef {
+                    // 3
+                    name: "idle_right".to_string(),
+                    row: 1,
+                    frames: 6,
+                    fps: 4,
+                },
+                AnimationDef {
+                    // 4
+                    name: "up".to_string(),
+                    row: 5,
+                    frames: 6,
+                    fps: 4,
+                },
+                AnimationDef {
+                    // 5
+                    name: "up_idle".to_string(),
+                    row: 2,
+                    frames: 6,
+                    fps: 4,
+                },
+                AnimationDef {
+                    // 6
+                    name: "attack_down".to_string(),
+                    row: 6,
+                    frames: 4,
+                    fps: 4,
+                },
+                AnimationDef {
+                    // 7
+                    name: "attack_sides".to_string(),
+                    row: 7,
+                    frames: 4,
+                    fps: 4,
+                },
+                AnimationDef {
+                    // 8
+                    name: "attack_up".to_string(),
+                    row: 8,
+                    frames: 4,
+                    fps: 4,
+                },
+                AnimationDef {
+                    // 9
+                    name: "death".to_string(),
+                    row: 9,
+                    frames: 3,
+                    fps: 4,
+                },
+            ],
+        };
+
+        let entity = self
+            .spawn_entity()
             .with(Sprite {
-                texture: crate::resources::load_and_set_filter("images/content/player.png").await?,
+                texture,
                 source_rect: Some(Rect::new(0.0, 0.0, 48.0, 48.0)),
                 dest_size: Some(Vec2::new(48.0, 48.0)),
-                animation: Some(AnimatedSprite::new(
-                    48,
-                    48,
-                    &[
-                        Animation {
-                            // 0
-                            name: "idle".to_string(),
-                            row: 0,
-                            frames: 6,
-                            fps: 4,
-                        },
-                        Animation {
-                            // 1
-                            name: "down".to_string(),
-                            row: 3,
-                            frames: 6,
-                            fps: 4,
-                        },
-                        Animation {
-                            // 2
-                            name: "right".to_string(),
-                            row: 4,
-                            frames: 6,
-                            fps: 4,
-                        },
-                        Animation {
-                            // 3
-                            name: "idle_right".to_string(),
-                            row: 1,
-                            frames: 6,
-                            fps: 4,
-                        },
-                        Animation {
-                            // 4
-                            name: "up".to_string(),
-                            row: 5,
-                            frames: 6,
-                            fps: 4,
-                        },
-                        Animation {
-                            // 5
-                            name: "up_idle".to_string(),
-                            row: 2,
-                            frames: 6,
-                            fps: 4,
-                        },
-                        Animation {
-                            // 6
-                            name: "attack_down".to_string(),
-                            row: 6,
-                            frames: 4,
-                            fps: 4,
-                        },
-                        Animation {
-                            // 7
-                            name: "attack_sides".to_string(),
-                            row: 7,
-                            frames: 4,
-                            fps: 4,
-                        },
-                        Animation {
-                            // 8
-                            name: "attack_up".to_string(),
-                            row: 8,
-                            frames: 4,
-                            fps: 4,
-                        },
-                        Animation {
-                            // 9
-                            name: "death".to_string(),
-                            row: 9,
-                            frames: 3,
-                            fps: 4,
-                        },
-                    ],
-                    true,
-                )),
+                animation: Some(sheet.animated_sprite()),
+                sheet: Some(sheet),
                 flipped: false,
                 last_animation: 0,
+                frame_timer: 0.0,
             })
             .with(Position { x, y })
+            .with(PreviousPosition { x, y })
             .with(Velocity::default())
             .with(Collider {
                 collision_offset: Vec2::new(17., 38.),
@@ -334,7 +916,119 @@ impl World {
                 sprite_padding: Vec2::new(18.0, 20.0),
                 visible_size: Vec2::new(18.0, 26.0),
             })
-            .with(Player::default());
-        Ok(())
+            .with(Contact::default())
+            .with(Player::default())
+            .with(Name::new("player"))
+            .id();
+
+        Ok(entity)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::World;
+    use crate::components::name::Name;
+
+    struct Health(i32);
+
+    #[test]
+    fn stale_handles_are_rejected() {
+        let mut world = World::new();
+        let old = world.spawn_entity().with(Health(1)).id();
+        world.despawn(old);
+        let new = world.spawn_entity().with(Health(2)).id();
+
+        // same slot, newer generation
+        assert_eq!(old.index, new.index);
+        assert!(!world.is_alive(old));
+        assert!(world.get_component::<Health>(old).is_none());
+        assert!(world.get_component_mut::<Health>(old).is_none());
+        assert!(world.query_one::<&Health>(old).is_none());
+        assert!(world.entity_mut(old).is_none());
+        assert!(!world.despawn(old));
+
+        assert_eq!(world.get_component::<Health>(new).unwrap().0, 2);
+    }
+
+    #[test]
+    fn name_index_follows_spawns_renames_and_despawns() {
+        let mut world = World::new();
+        let first = world.spawn_entity().with(Name::new("slime")).id();
+        let second = world.spawn_entity().with(Name::new("slime")).id();
+        let other = world.spawn_entity().with(Name::new("bat")).id();
+
+        assert_eq!(world.find_by_name("slime"), Some(first));
+        assert_eq!(world.find_all_by_name("slime"), &[first, second]);
+
+        world.despawn(first);
+        assert_eq!(world.find_all_by_name("slime"), &[second]);
+
+        world.add_component_to_entity(other, Name::new("slime"));
+        assert_eq!(world.find_all_by_name("slime"), &[second, other]);
+        assert_eq!(world.find_by_name("bat"), None);
+    }
+
+    #[test]
+    fn tiled_ids_are_dropped_on_despawn() {
+        let mut world = World::new();
+        let tree = world.add_entity();
+        let rock = world.add_entity();
+        world.map_tiled_object(5, tree);
+        world.map_tiled_object(6, rock);
+
+        assert_eq!(world.tiled_object(5), Some(tree));
+        assert_eq!(world.tiled_object_id(rock), Some(6));
+
+        world.despawn(tree);
+        assert_eq!(world.tiled_object(5), None);
+        assert_eq!(world.tiled_object_id(tree), None);
+
+        // the slot gets reused, the id doesn't come with it
+        let reused = world.add_entity();
+        assert_eq!(reused.index, tree.index);
+        assert_eq!(world.tiled_object_id(reused), None);
+
+        // mapping an id again moves it
+        world.map_tiled_object(6, reused);
+        assert_eq!(world.tiled_object(6), Some(reused));
+        assert_eq!(world.tiled_object_id(rock), None);
+    }
+
+    #[test]
+    fn despawning_a_parent_despawns_its_children() {
+        let mut world = World::new();
+        let root = world.add_entity();
+        let child = world.add_entity();
+        let grandchild = world.add_entity();
+        let other = world.add_entity();
+        world.set_parent(child, root);
+        world.set_parent(grandchild, child);
+
+        world.despawn(root);
+
+        assert!(!world.is_alive(child));
+        assert!(!world.is_alive(grandchild));
+        assert!(world.is_alive(other));
+    }
+
+    #[test]
+    fn set_parent_rejects_cycles() {
+        let mut world = World::new();
+        let root = world.add_entity();
+        let child = world.add_entity();
+        let grandchild = world.add_entity();
+        assert!(world.set_parent(child, root));
+        assert!(world.set_parent(grandchild, child));
+
+        assert!(!world.set_parent(root, grandchild));
+        assert!(!world.set_parent(root, root));
+        assert_eq!(world.parent(root), None);
+        assert!(world.children(grandchild).is_empty());
+
+        // moving a child somewhere else is fine
+        assert!(world.set_parent(grandchild, root));
+        assert_eq!(world.children(root), &[child, grandchild]);
+        assert!(world.children(child).is_empty());
     }
 }
@@ -0,0 +1,163 @@
+use rustling::{
+    components::{enemy::Enemy, position::Position},
+    events::PlayerHit,
+    headless::HeadlessGame,
+    replay::{self, record_system, ReplayRecorder},
+    resources::{FixedTime, InputState},
+    save::SaveSlot,
+    schedule::Stage,
+    systems::systems::save_load_system,
+};
+
+const DT: f32 = 1.0 / 60.0;
+
+/// The player with a few enemies around, two of them from the same spot.
+fn populate(game: &mut HeadlessGame) {
+    game.world.spawn_player(300.0, 300.0).unwrap();
+    game.world.spawn_enemy(200.0, 200.0, "skeleton").unwrap();
+    game.world.spawn_enemy(450.0, 250.0, "slime").unwrap();
+    game.world.spawn_enemy(450.0, 250.0, "slime").unwrap();
+}
+
+#[test]
+fn skeleton_in_range_attacks_after_attack_interval() {
+    let mut game = HeadlessGame::new(1).unwrap();
+    let player = game.world.spawn_player(300.0, 300.0).unwrap();
+    let skeleton = game.world.spawn_enemy(300.0, 300.0, "skeleton").unwrap();
+
+    // spawned enemies attack right away, start the wait from zero instead
+    let enemy = game.world.get_component_mut::<Enemy>(skeleton).unwrap();
+    enemy.attack_timer = 0.0;
+    let interval = enemy.attack_interval;
+
+    let mut attacked_at = None;
+    let mut hit = false;
+    for frame in 1..=120 {
+        // attacking lunges at the player, keep it in range
+        *game.world.get_component_mut::<Position>(skeleton).unwrap() =
+            Position { x: 300.0, y: 300.0 };
+        game.step(DT);
+
+        let enemy = game.world.get_component::<Enemy>(skeleton).unwrap();
+        if enemy.attacking && attacked_at.is_none() {
+            attacked_at = Some(frame as f32 * DT);
+        }
+        hit |= game
+            .world
+            .event_reader::<PlayerHit>()
+            .iter()
+            .any(|event| event.player == player && event.enemy == skeleton);
+    }
+
+    let attacked_at = attacked_at.expect("the skeleton never attacked");
+    assert!(
+        attacked_at >= interval && attacked_at < interval + 2.0 * DT,
+        "attacked after {}s, attack_interval is {}s",
+        attacked_at,
+        interval
+    );
+    assert!(hit, "the attack didn't hit the player");
+}
+
+#[test]
+fn recorded_session_replays_to_the_same_checksum() {
+    let seed = 7;
+    // a save to start the session from, playback starts from it too
+    let mut source = HeadlessGame::new(seed).unwrap();
+    populate(&mut source);
+    let mut slot = SaveSlot::Memory(None);
+    slot.save(&source.world).unwrap();
+
+    let mut game = HeadlessGame::new(seed).unwrap();
+    game.world.insert_resource(slot);
+    let max_steps = game.world.resource::<FixedTime>().max_steps;
+    let save = game.world.resource::<SaveSlot>().contents().ok();
+    game.world
+        .insert_resource(ReplayRecorder::new(seed, max_steps, save));
+    game.schedule
+        .add_system(Stage::PreUpdate, record_system)
+        .before(save_load_system);
+
+    // load, walk, attack, save, walk off and load back, with uneven frame
+    // times
+    let script: [(usize, InputState); 6] = [
+        (
+            1,
+            InputState {
+                load: true,
+                ..Default::default()
+            },
+        ),
+        (
+            40,
+            InputState {
+                right: true,
+                down: true,
+                ..Default::default()
+            },
+        ),
+        (
+            10,
+            InputState {
+                attack: true,
+                ..Default::default()
+            },
+        ),
+        (
+            1,
+            InputState {
+                save: true,
+                ..Default::default()
+            },
+        ),
+        (
+            30,
+            InputState {
+                left: true,
+                ..Default::default()
+            },
+        ),
+        (
+            1,
+            InputState {
+                load: true,
+                ..Default::default()
+            },
+        ),
+    ];
+    for (i, (frames, input)) in script.into_iter().enumerate() {
+        *game.input_mut() = input;
+        for frame in 0..frames {
+            game.step(if (i + frame) % 3 == 0 { 0.025 } else { DT });
+        }
+    }
+    *game.input_mut() = InputState::default();
+    game.run(20, DT);
+
+    assert_eq!(game.world.query::<&Enemy>().count(), 3);
+    let checksum = game.world.checksum().unwrap();
+    let mut replay = game.world.resource::<ReplayRecorder>().replay.clone();
+    replay.checksum = Some(checksum);
+    assert_eq!(replay.frames.len(), 103);
+
+    assert_eq!(replay::verify(&replay).unwrap(), checksum);
+}
+
+#[test]
+fn same_seed_gives_the_same_world() {
+    let run = |seed| {
+        let mut game = HeadlessGame::new(seed).unwrap();
+        populate(&mut game);
+        *game.input_mut() = InputState {
+            right: true,
+            attack: true,
+            ..Default::default()
+        };
+        game.run(300, DT);
+        game.world.checksum().unwrap()
+    };
+
+    assert_eq!(run(42), run(42));
+    // enemies wander on their own streams, another seed sends them elsewhere
+    assert_ne!(run(42), run(43));
+}
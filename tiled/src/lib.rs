@@ -13,6 +13,9 @@ pub use tiled::{Property, PropertyVal};
 
 #[derive(Debug, Clone)]
 pub struct Object {
+    /// Unique id Tiled gave the object, stable across edits of the map
+    pub id: u32,
+
     /// If not null - the object is (probably) a tile
     pub gid: Option<u32>,
 
@@ -29,6 +32,25 @@ pub struct Object {
     pub name: String,
 
     pub properties: HashMap<String, String>,
+
+    /// Outline of the object, relative to (world_x, world_y) and before rotation
+    pub shape: ObjectShape,
+    /// Degrees, clockwise around (world_x, world_y)
+    pub rotation: f32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ObjectShape {
+    /// world_w x world_h box, also what tile objects are
+    Rect,
+    /// Fits the world_w x world_h box
+    Ellipse,
+    /// Closed outline
+    Polygon(Vec<Vec2>),
+    /// Open outline
+    Polyline(Vec<Vec2>),
+    /// Just (world_x, world_y)
+    Point,
 }
 
 /// Flip operation application order:
@@ -261,7 +283,7 @@ impl Map {
         );
     }
 
-    pub fn tiles(&self, layer: &str, rect: impl Into<Option<Rect>>) -> TilesIterator {
+    pub fn tiles(&self, layer: &str, rect: impl Into<Option<Rect>>) -> TilesIterator<'_> {
         assert!(self.layers.contains_key(layer), "No such layer: {}", layer);
 
         let rect = rect.into().unwrap_or(Rect::new(
@@ -333,6 +355,24 @@ impl<'a> Iterator for TilesIterator<'a> {
     }
 }
 
+fn object_shape(object: &tiled::layer::Object) -> ObjectShape {
+    let points = |points: &[tiled::layer::PolyPoint]| {
+        points.iter().map(|point| vec2(point.x, point.y)).collect()
+    };
+
+    if object.point == Some(true) {
+        ObjectShape::Point
+    } else if object.ellipse == Some(true) {
+        ObjectShape::Ellipse
+    } else if let Some(polygon) = &object.polygon {
+        ObjectShape::Polygon(points(polygon))
+    } else if let Some(polyline) = &object.polyline {
+        ObjectShape::Polyline(points(polyline))
+    } else {
+        ObjectShape::Rect
+    }
+}
+
 /// Load Tiled tile map from given json string.
 /// "data" is a tiled json content.
 /// "textures" is a map from the name used in the json to macroquad texture.
@@ -402,6 +442,7 @@ pub fn load_map(
         let mut objects = vec![];
         for object in &layer.objects {
             objects.push(Object {
+                id: object.id,
                 gid: object.gid,
                 world_x: object.x,
                 world_y: object.y,
@@ -418,6 +459,8 @@ pub fn load_map(
                     .iter()
                     .map(|property| (property.name.to_string(), property.value.to_string()))
                     .collect(),
+                shape: object_shape(object),
+                rotation: object.rotation,
             });
         }
 
@@ -62,7 +62,9 @@ pub struct Object {
     pub ty: String,
     pub gid: Option<u32>,
     pub ellipse: Option<bool>,
+    pub point: Option<bool>,
     pub polygon: Option<Vec<PolyPoint>>,
+    pub polyline: Option<Vec<PolyPoint>>,
 
     pub properties: Vec<Property>,
     pub rotation: f32,